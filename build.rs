@@ -1,9 +1,11 @@
 use std::ffi::OsString;
+use std::path::PathBuf;
 use std::process::{self, Command};
-use std::{env, str};
+use std::{env, fs, str};
 
 pub fn main() {
     println!("cargo:rerun-if-changed=build.rs");
+    write_default_strategy();
     let rustc = match rustc_minor_version() {
         Some(x) => x,
         None => return,
@@ -11,13 +13,77 @@ pub fn main() {
 
     if rustc >= 80 {
         println!("cargo:rustc-check-cfg=cfg(nounwind_extern_c_will_abort)");
+        println!("cargo:rustc-check-cfg=cfg(nounwind_label_break_value)");
+        println!("cargo:rustc-check-cfg=cfg(nounwind_asm)");
+        println!("cargo:rustc-check-cfg=cfg(nounwind_raw_syscalls)");
+        println!("cargo:rustc-check-cfg=cfg(nounwind_panic_info_message)");
+        println!("cargo:rustc-check-cfg=cfg(nounwind_std_backtrace)");
+    }
+
+    // used by the strategies in `nounwind::terminate`
+    if rustc >= 59 {
+        println!("cargo:rustc-cfg=nounwind_asm");
+        let os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+        if os == "linux" && (arch == "x86_64" || arch == "aarch64") {
+            println!("cargo:rustc-cfg=nounwind_raw_syscalls");
+        }
+    }
+
+    if rustc >= 65 {
+        // used by `#[nounwind] const fn`
+        println!("cargo:rustc-cfg=nounwind_label_break_value");
+        // used by crash reports
+        println!("cargo:rustc-cfg=nounwind_std_backtrace");
     }
 
     if rustc >= 81 {
         println!("cargo:rustc-cfg=nounwind_extern_c_will_abort");
+        // used by the `panic-handler` feature
+        println!("cargo:rustc-cfg=nounwind_panic_info_message");
     }
 }
 
+/// Write the default `nounwind::terminate::Strategy`,
+/// as selected by the `NOUNWIND_ABORT_STRATEGY` environment variable.
+fn write_default_strategy() {
+    println!("cargo:rerun-if-env-changed=NOUNWIND_ABORT_STRATEGY");
+    let value = env::var("NOUNWIND_ABORT_STRATEGY").unwrap_or_default();
+    let strategy = match parse_strategy(value.trim()) {
+        Some(strategy) => strategy,
+        None => {
+            eprintln!(
+                "Invalid $NOUNWIND_ABORT_STRATEGY {:?}, expected one of `abort`, `trap`, `sigabrt`, `signal=N`, or `exit=N`",
+                value,
+            );
+            process::exit(1);
+        }
+    };
+    let out_dir = PathBuf::from(cargo_env_var("OUT_DIR"));
+    fs::write(out_dir.join("default_strategy.rs"), strategy).unwrap_or_else(|err| {
+        eprintln!("Failed to write default strategy: {}", err);
+        process::exit(1);
+    });
+}
+
+fn parse_strategy(value: &str) -> Option<String> {
+    Some(match value {
+        "" | "abort" => "Strategy::Abort".into(),
+        "trap" => "Strategy::Trap".into(),
+        "sigabrt" => "Strategy::Signal(6)".into(),
+        _ => {
+            let mut parts = value.splitn(2, '=');
+            let kind = parts.next()?;
+            let code = parts.next()?.trim().parse::<i32>().ok()?;
+            match kind.trim() {
+                "signal" => format!("Strategy::Signal({})", code),
+                "exit" => format!("Strategy::Exit({})", code),
+                _ => return None,
+            }
+        }
+    })
+}
+
 // Copied from anyhow@1.0.100/build.rs: <https://github.com/dtolnay/anyhow/blob/1.0.100/build.rs#L213-L232>
 // This has the same license that we do (MIT OR APACHE-2.0)
 fn rustc_minor_version() -> Option<u32> {
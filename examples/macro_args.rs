@@ -0,0 +1,20 @@
+use nounwind::{nounwind, EscapedPanic};
+
+pub fn main() {
+    check_invariant(3);
+    check_invariant(0);
+}
+
+fn report(panic: &EscapedPanic<'_>) {
+    eprintln!(
+        "on_panic handler called for boundary at {} (message: {:?})",
+        panic.location(),
+        panic.message()
+    );
+}
+
+#[nounwind(message = "invariant of check_invariant broken", on_panic = report)]
+fn check_invariant(x: u32) -> u32 {
+    assert!(x > 0, "x must be positive");
+    x
+}
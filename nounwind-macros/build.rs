@@ -0,0 +1,34 @@
+use std::env;
+use std::process::Command;
+use std::str;
+
+pub fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let rustc = match rustc_minor_version() {
+        Some(x) => x,
+        None => return,
+    };
+
+    if rustc >= 80 {
+        println!("cargo:rustc-check-cfg=cfg(nounwind_label_break_value)");
+    }
+
+    // `#[nounwind] const fn` expands to a labeled block,
+    // so it is rejected with a clear error on older versions
+    if rustc >= 65 {
+        println!("cargo:rustc-cfg=nounwind_label_break_value");
+    }
+}
+
+// Copied from anyhow@1.0.100/build.rs: <https://github.com/dtolnay/anyhow/blob/1.0.100/build.rs#L213-L232>
+// This has the same license that we do (MIT OR APACHE-2.0)
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = str::from_utf8(&output.stdout).ok()?;
+    let mut pieces = version.split('.');
+    if pieces.next() != Some("rustc 1") {
+        return None;
+    }
+    pieces.next()?.parse().ok()
+}
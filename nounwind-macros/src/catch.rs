@@ -0,0 +1,57 @@
+//! Implementation of the `#[catch_unwind(return = value)]` attribute.
+
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::meta::ParseNestedMeta;
+use syn::parse_quote;
+
+/// The arguments accepted by the `#[catch_unwind(...)]` attribute.
+#[derive(Default)]
+pub struct CatchArgs {
+    /// The value to return if the function panics.
+    value: Option<syn::Expr>,
+    /// The path to the `nounwind` crate.
+    krate: Option<syn::Path>,
+}
+impl CatchArgs {
+    pub fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("return") {
+            crate::set_once(&meta, &mut self.value, meta.value()?.parse()?)
+        } else if meta.path.is_ident("crate") {
+            crate::set_once(&meta, &mut self.krate, meta.value()?.parse()?)
+        } else {
+            Err(meta.error("unsupported #[catch_unwind] argument"))
+        }
+    }
+}
+
+/// Wrap the body of a function to return the specified value if it panics.
+pub fn catch_unwind(args: CatchArgs, mut item: syn::ItemFn) -> syn::Result<TokenStream> {
+    let value = match args.value {
+        Some(value) => value,
+        None => {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "#[catch_unwind] requires a value, for example #[catch_unwind(return = -1)]",
+            ))
+        }
+    };
+    if let Some(asyncness) = item.sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "#[catch_unwind] does not support async functions",
+        ));
+    }
+    if let Some(constness) = item.sig.constness {
+        return Err(syn::Error::new_spanned(
+            constness,
+            "#[catch_unwind] does not support const functions",
+        ));
+    }
+    let krate = args.krate.unwrap_or_else(crate::default_crate_path);
+    let old_block = item.block.clone();
+    *item.block = parse_quote!({
+        #krate::catch::catch_to(#value, #[inline(always)] move || #old_block)
+    });
+    Ok(item.into_token_stream())
+}
@@ -0,0 +1,73 @@
+//! Support for `#[nounwind] const fn`.
+//!
+//! A `const fn` cannot call closures, so the body cannot be passed to `abort_unwind`.
+//! Instead, the body is guarded by a drop guard which is defused once the body completes.
+//! Any `return` expressions in the body are rewritten to break out of a labeled block,
+//! so they cannot skip defusing the guard.
+//!
+//! A `return` hidden inside a macro invocation cannot be rewritten.
+//! This is still safe, because dropping the guard in a `const fn` is a compile error.
+//!
+//! Labeled blocks require Rust 1.65, so older versions reject `#[nounwind] const fn`.
+
+use proc_macro2::Span;
+use syn::visit_mut::VisitMut;
+use syn::{parse_quote, Lifetime};
+
+use crate::Args;
+
+pub(crate) fn wrap_const_block(args: &Args, sig: &syn::Signature, block: &mut syn::Block) {
+    if cfg!(not(nounwind_label_break_value)) {
+        let error = syn::Error::new_spanned(
+            sig.constness,
+            "#[nounwind] const fn requires Rust 1.65 or later",
+        )
+        .to_compile_error();
+        *block = parse_quote!({ #error });
+        return;
+    }
+    let krate = args.crate_path();
+    let info = args.info(sig);
+    // use mixed-site hygiene, so these can't conflict with names in the body
+    let label = Lifetime {
+        apostrophe: Span::mixed_site(),
+        ident: syn::Ident::new("__nounwind_body", Span::mixed_site()),
+    };
+    let guard = syn::Ident::new("__nounwind_guard", Span::mixed_site());
+    let result = syn::Ident::new("__nounwind_result", Span::mixed_site());
+    let mut old_block = std::mem::replace(block, parse_quote!({ compile_error!("dummy value") }));
+    ReplaceReturn { label: &label }.visit_block_mut(&mut old_block);
+    *block = parse_quote!({
+        let #guard = #krate::panic_internals::ConstGuard::new(#info);
+        let #result = #label: #old_block;
+        #guard.defuse();
+        #result
+    });
+}
+
+/// Replace `return` expressions with `break` from a labeled block.
+struct ReplaceReturn<'a> {
+    label: &'a Lifetime,
+}
+impl VisitMut for ReplaceReturn<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        match expr {
+            // these contain their own `return` scope
+            syn::Expr::Closure(_) | syn::Expr::Async(_) => {}
+            syn::Expr::Return(ret) => {
+                if let Some(ref mut value) = ret.expr {
+                    self.visit_expr_mut(value);
+                }
+                let label = self.label;
+                let value = ret.expr.take();
+                let attrs = std::mem::take(&mut ret.attrs);
+                *expr = parse_quote!(#(#attrs)* break #label #value);
+            }
+            _ => syn::visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {
+        // nested items contain their own `return` scope
+    }
+}
@@ -1,14 +1,169 @@
 //! The underlying implementation of the `#[nounwind]` attribute macro.
 
 use proc_macro2::TokenStream;
-use quote::ToTokens;
-use syn::parse::{Parse, ParseStream};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::meta::ParseNestedMeta;
 use syn::{parse_macro_input, parse_quote};
 
-struct Empty;
-impl Parse for Empty {
-    fn parse(_input: ParseStream) -> syn::Result<Self> {
-        Ok(Empty)
+mod catch;
+mod const_fn;
+mod wrapper;
+
+/// The arguments accepted by the `#[nounwind(...)]` attribute.
+#[derive(Clone, Default)]
+struct Args {
+    /// Leave the item unchanged.
+    ///
+    /// Used to opt a single method out of a `#[nounwind]` impl block.
+    skip: bool,
+    /// A message to print before aborting.
+    message: Option<syn::LitStr>,
+    /// A function to call before aborting,
+    /// which is given a `nounwind::EscapedPanic`.
+    on_panic: Option<syn::Expr>,
+    /// Only abort if this boolean expression is true,
+    /// similar to the C++ `noexcept(expr)` specifier.
+    condition: Option<syn::Expr>,
+    /// Only apply the attribute if this `cfg` predicate holds.
+    cfg: Option<TokenStream>,
+    /// Leave the function unchanged,
+    /// and generate a separate wrapper function with this name.
+    wrapper: Option<syn::Ident>,
+    /// The ABI of the wrapper function, for example `"C"`.
+    abi: Option<syn::LitStr>,
+    /// Mark the wrapper function `#[no_mangle]`.
+    no_mangle: bool,
+    /// The path to the `nounwind` crate.
+    ///
+    /// If this is not specified, it is found by searching the `Cargo.toml` file.
+    krate: Option<syn::Path>,
+}
+impl Args {
+    fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("skip") {
+            self.skip = true;
+        } else if meta.path.is_ident("message") {
+            set_once(&meta, &mut self.message, meta.value()?.parse()?)?;
+        } else if meta.path.is_ident("on_panic") {
+            set_once(&meta, &mut self.on_panic, meta.value()?.parse()?)?;
+        } else if meta.path.is_ident("if") {
+            set_once(&meta, &mut self.condition, meta.value()?.parse()?)?;
+        } else if meta.path.is_ident("cfg") {
+            let content;
+            syn::parenthesized!(content in meta.input);
+            set_once(&meta, &mut self.cfg, content.parse()?)?;
+        } else if meta.path.is_ident("wrapper") {
+            set_once(&meta, &mut self.wrapper, meta.value()?.parse()?)?;
+        } else if meta.path.is_ident("abi") {
+            set_once(&meta, &mut self.abi, meta.value()?.parse()?)?;
+        } else if meta.path.is_ident("no_mangle") {
+            self.no_mangle = true;
+        } else if meta.path.is_ident("crate") {
+            set_once(&meta, &mut self.krate, meta.value()?.parse()?)?;
+        } else {
+            return Err(meta.error("unsupported #[nounwind] argument"));
+        }
+        Ok(())
+    }
+
+    /// The path to the `nounwind` crate.
+    fn crate_path(&self) -> syn::Path {
+        match self.krate {
+            Some(ref path) => path.clone(),
+            None => default_crate_path(),
+        }
+    }
+
+    /// The runtime configuration of the function with the specified signature.
+    fn info(&self, sig: &syn::Signature) -> TokenStream {
+        let krate = self.crate_path();
+        let enabled = match self.condition {
+            Some(ref condition) => quote!(#condition),
+            None => quote!(true),
+        };
+        let message = option_tokens(self.message.as_ref());
+        let on_panic = option_tokens(self.on_panic.as_ref());
+        let name = name_fn(&krate, sig);
+        let location = location_fn(sig);
+        quote!(#krate::panic_internals::NounwindFn {
+            enabled: #enabled,
+            message: #message,
+            on_panic: #on_panic,
+            name: #name,
+            location: #location,
+        })
+    }
+
+    fn from_attr(attr: &syn::Attribute) -> syn::Result<Self> {
+        let mut args = Args::default();
+        match attr.meta {
+            syn::Meta::Path(_) => {}
+            _ => attr.parse_nested_meta(|meta| args.parse_meta(meta))?,
+        }
+        Ok(args)
+    }
+}
+
+/// Find the path to the `nounwind` crate, accounting for renamed dependencies.
+///
+/// Falls back to `::nounwind` if the crate is not a direct dependency,
+/// in which case it must be specified explicitly using `#[nounwind(crate = path)]`.
+fn default_crate_path() -> syn::Path {
+    use proc_macro_crate::FoundCrate;
+    match proc_macro_crate::crate_name("nounwind") {
+        Ok(FoundCrate::Name(name)) => {
+            let ident = syn::Ident::new(&name, proc_macro2::Span::call_site());
+            parse_quote!(::#ident)
+        }
+        // The nounwind crate never uses its own macro,
+        // so this must be an example or a doctest.
+        Ok(FoundCrate::Itself) | Err(_) => parse_quote!(::nounwind),
+    }
+}
+
+/// A function returning the fully qualified path of the function with the specified signature.
+///
+/// The path is found using the type name of a nested function,
+/// which includes the names of any enclosing impl blocks and traits.
+fn name_fn(krate: &syn::Path, sig: &syn::Signature) -> TokenStream {
+    let name = syn::Ident::new("__nounwind_name", sig.ident.span());
+    quote!({
+        fn #name() -> &'static str {
+            #krate::panic_internals::type_name_of(#name)
+        }
+        #name
+    })
+}
+
+/// A function returning the location of the function with the specified signature.
+///
+/// This is a separate function so it is unaffected by `#[track_caller]`,
+/// and can be used by a `const fn`.
+fn location_fn(sig: &syn::Signature) -> TokenStream {
+    quote_spanned!(sig.ident.span()=> {
+        fn location() -> &'static ::core::panic::Location<'static> {
+            ::core::panic::Location::caller()
+        }
+        location
+    })
+}
+
+/// Set an attribute argument, which must not be given more than once.
+fn set_once<T>(meta: &ParseNestedMeta, dest: &mut Option<T>, value: T) -> syn::Result<()> {
+    if dest.is_some() {
+        return Err(meta.error(format_args!(
+            "duplicate argument `{}`",
+            meta.path.to_token_stream()
+        )));
+    }
+    *dest = Some(value);
+    Ok(())
+}
+
+fn option_tokens(value: Option<&impl ToTokens>) -> TokenStream {
+    match value {
+        Some(value) => quote!(::core::option::Option::Some(#value)),
+        None => quote!(::core::option::Option::None),
     }
 }
 
@@ -17,22 +172,298 @@ pub fn nounwind(
     attr: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let _: Empty = parse_macro_input!(attr as Empty);
-    let input = parse_macro_input!(item as syn_mid::ItemFn);
-    do_nounwind(input)
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| args.parse_meta(meta));
+    parse_macro_input!(attr with parser);
+    let input = parse_macro_input!(item as syn::Item);
+    do_nounwind(&args, input)
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
 
-fn do_nounwind(mut item: syn_mid::ItemFn) -> syn::Result<TokenStream> {
-    let old_block = std::mem::replace(
-        &mut item.block,
-        Box::new(parse_quote!({ compile_error!("dummy value") })),
-    );
-    item.block = Box::new(parse_quote!({
-        nounwind::abort_unwind(#[inline(always)] move || {
-            #old_block
+#[proc_macro_attribute]
+pub fn catch_unwind(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let mut args = catch::CatchArgs::default();
+    let parser = syn::meta::parser(|meta| args.parse_meta(meta));
+    parse_macro_input!(attr with parser);
+    let input = parse_macro_input!(item as syn::ItemFn);
+    catch::catch_unwind(args, input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro]
+pub fn closure(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as ClosureInput);
+    do_closure(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The input to `closure!`, with an optional `crate = path,` prefix.
+struct ClosureInput {
+    /// The path to the `nounwind` crate.
+    krate: Option<syn::Path>,
+    closure: syn::ExprClosure,
+}
+impl syn::parse::Parse for ClosureInput {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let krate = if input.peek(syn::Token![crate]) && input.peek2(syn::Token![=]) {
+            input.parse::<syn::Token![crate]>()?;
+            input.parse::<syn::Token![=]>()?;
+            let path = input.parse()?;
+            input.parse::<syn::Token![,]>()?;
+            Some(path)
+        } else {
+            None
+        };
+        Ok(ClosureInput {
+            krate,
+            closure: input.parse()?,
         })
-    }));
+    }
+}
+
+/// Wrap the body of a closure to abort on unwinding.
+///
+/// The body is wrapped in a non-`move` closure,
+/// so the outer closure implements the same `Fn*` traits as the original.
+fn do_closure(input: ClosureInput) -> syn::Result<TokenStream> {
+    let ClosureInput { krate, mut closure } = input;
+    if let Some(asyncness) = closure.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "nounwind::closure! does not support async closures, use an async block with AbortOnUnwind",
+        ));
+    }
+    if let Some(constness) = closure.constness {
+        return Err(syn::Error::new_spanned(
+            constness,
+            "nounwind::closure! does not support const closures",
+        ));
+    }
+    if let Some(movability) = closure.movability {
+        return Err(syn::Error::new_spanned(
+            movability,
+            "nounwind::closure! does not support static closures",
+        ));
+    }
+    let krate = krate.unwrap_or_else(default_crate_path);
+    let body = &closure.body;
+    let wrapped: syn::Expr = parse_quote!({
+        #krate::abort_unwind(#[inline(always)] || #body)
+    });
+    closure.body = Box::new(wrapped);
+    Ok(closure.into_token_stream())
+}
+
+fn do_nounwind(args: &Args, item: syn::Item) -> syn::Result<TokenStream> {
+    if args.skip {
+        return Ok(item.into_token_stream());
+    }
+    if args.wrapper.is_none() && (args.abi.is_some() || args.no_mangle) {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[nounwind(abi = ...)] and #[nounwind(no_mangle)] require #[nounwind(wrapper = name)]",
+        ));
+    }
+    if args.wrapper.is_some() {
+        return match item {
+            syn::Item::Fn(item) => Ok(wrapper::with_wrapper(args, item)),
+            other => Err(syn::Error::new_spanned(
+                other,
+                "#[nounwind(wrapper = name)] can only be applied to functions",
+            )),
+        };
+    }
+    if let Some(ref cfg) = args.cfg {
+        let enabled_args = Args {
+            cfg: None,
+            ..args.clone()
+        };
+        let enabled = do_nounwind(&enabled_args, item.clone())?;
+        return Ok(quote! {
+            #[cfg(#cfg)]
+            #enabled
+            #[cfg(not(#cfg))]
+            #item
+        });
+    }
+    match item {
+        syn::Item::Fn(mut item) => {
+            wrap_block(args, &item.attrs, &mut item.sig, &mut item.block);
+            Ok(item.into_token_stream())
+        }
+        syn::Item::Impl(item) => nounwind_impl(args, item),
+        syn::Item::Trait(item) => nounwind_trait(args, item),
+        // syn doesn't recognize a required trait method as an item
+        syn::Item::Verbatim(tokens) => match syn::parse2::<syn::TraitItemFn>(tokens.clone()) {
+            Ok(method) => Ok(nounwind_trait_method(args, method)),
+            Err(_) => Err(unsupported_item(tokens)),
+        },
+        other => Err(unsupported_item(other)),
+    }
+}
+
+fn unsupported_item(item: impl ToTokens) -> syn::Error {
+    syn::Error::new_spanned(
+        item,
+        "#[nounwind] can only be applied to functions, impl blocks, and traits",
+    )
+}
+
+/// Apply `#[nounwind]` to every method in an impl block.
+fn nounwind_impl(args: &Args, mut item: syn::ItemImpl) -> syn::Result<TokenStream> {
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            syn::ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        if applies_to_nested(&mut method.attrs)? {
+            wrap_block(args, &method.attrs, &mut method.sig, &mut method.block);
+        }
+    }
+    Ok(item.into_token_stream())
+}
+
+/// Apply `#[nounwind]` to every method in a trait definition.
+///
+/// Default method bodies are wrapped just like functions.
+/// Required methods document that implementations must not unwind.
+fn nounwind_trait(args: &Args, mut item: syn::ItemTrait) -> syn::Result<TokenStream> {
+    for trait_item in &mut item.items {
+        let method = match trait_item {
+            syn::TraitItem::Fn(method) => method,
+            _ => continue,
+        };
+        if !applies_to_nested(&mut method.attrs)? {
+            continue;
+        }
+        match method.default {
+            Some(ref mut block) => wrap_block(args, &method.attrs, &mut method.sig, block),
+            None => add_contract_docs(&mut method.attrs),
+        }
+    }
     Ok(item.into_token_stream())
 }
+
+/// Apply `#[nounwind]` to a method inside a trait definition.
+///
+/// This is only needed for required methods,
+/// as methods with a body are parsed as regular functions.
+fn nounwind_trait_method(args: &Args, mut method: syn::TraitItemFn) -> TokenStream {
+    match method.default {
+        Some(ref mut block) => wrap_block(args, &method.attrs, &mut method.sig, block),
+        None => add_contract_docs(&mut method.attrs),
+    }
+    method.into_token_stream()
+}
+
+/// Document that implementations of a required trait method must not unwind.
+fn add_contract_docs(attrs: &mut Vec<syn::Attribute>) {
+    attrs.push(parse_quote!(#[doc = ""]));
+    attrs.push(parse_quote!(#[doc = " # Unwinding"]));
+    attrs.push(parse_quote!(#[doc = " Implementations of this method must not unwind."]));
+    attrs.push(parse_quote!(#[doc = " Apply `#[nounwind]` to the `impl` block to enforce this."]));
+}
+
+/// Check if the `#[nounwind]` attribute on an impl block or trait
+/// should apply to one of the methods it contains.
+///
+/// Methods with their own `#[nounwind(...)]` attribute are left alone,
+/// so that attribute can be expanded separately.
+/// Methods marked `#[nounwind(skip)]` are left unchanged,
+/// and the marker attribute is removed.
+fn applies_to_nested(attrs: &mut Vec<syn::Attribute>) -> syn::Result<bool> {
+    match attrs.iter().position(is_nounwind_attr) {
+        Some(index) => {
+            if Args::from_attr(&attrs[index])?.skip {
+                attrs.remove(index);
+            }
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Check if an attribute is a (possibly qualified) `#[nounwind]` attribute.
+fn is_nounwind_attr(attr: &syn::Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .map_or(false, |segment| segment.ident == "nounwind")
+}
+
+/// Wrap the body of a function to abort on unwinding.
+///
+/// For an `async fn`, this guards each poll of the future rather than its construction,
+/// and moves every argument into the guarded future.
+/// A `const fn` uses a drop guard, as it cannot call closures.
+/// A `#[track_caller]` function runs its body inline where possible,
+/// as closures cannot track the caller.
+fn wrap_block(
+    args: &Args,
+    attrs: &[syn::Attribute],
+    sig: &mut syn::Signature,
+    block: &mut syn::Block,
+) {
+    if sig.constness.is_some() {
+        const_fn::wrap_const_block(args, sig, block);
+        return;
+    }
+    let old_block = std::mem::replace(block, parse_quote!({ compile_error!("dummy value") }));
+    let krate = args.crate_path();
+    let info = args.info(sig);
+    let track_caller = attrs
+        .iter()
+        .any(|attr| attr.path().is_ident("track_caller"));
+    *block = if sig.asyncness.is_some() {
+        let captures = capture_inputs(sig);
+        parse_quote!({
+            #krate::future::AbortOnUnwind::with_info(async move { #(#captures)* #old_block }, #info).await
+        })
+    } else if track_caller {
+        parse_quote!({
+            #krate::__track_caller_nounwind!(#info, #old_block)
+        })
+    } else {
+        parse_quote!({
+            #krate::panic_internals::call_nounwind(&#info, #[inline(always)] move || #old_block)
+        })
+    };
+}
+
+/// Move every argument of an `async fn` into the guarded future.
+///
+/// Otherwise, arguments which are not used by the body would stay in the outer future,
+/// and be dropped outside the guard.
+/// Patterns other than simple identifiers are replaced by a generated name,
+/// and destructured inside the guarded future.
+fn capture_inputs(sig: &mut syn::Signature) -> Vec<syn::Stmt> {
+    let mut captures = Vec::new();
+    for (index, input) in sig.inputs.iter_mut().enumerate() {
+        let typed = match input {
+            syn::FnArg::Receiver(_) => {
+                captures.push(parse_quote!(let _ = &self;));
+                continue;
+            }
+            syn::FnArg::Typed(typed) => typed,
+        };
+        match *typed.pat {
+            syn::Pat::Ident(ref pat) if pat.subpat.is_none() && pat.by_ref.is_none() => {
+                let ident = &pat.ident;
+                captures.push(parse_quote!(let _ = &#ident;));
+            }
+            _ => {
+                let ident =
+                    syn::Ident::new(&format!("__arg{}", index), proc_macro2::Span::call_site());
+                let pat = std::mem::replace(&mut *typed.pat, parse_quote!(#ident));
+                captures.push(parse_quote!(let #pat = #ident;));
+            }
+        }
+    }
+    captures
+}
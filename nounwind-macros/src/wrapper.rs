@@ -0,0 +1,167 @@
+//! Support for `#[nounwind(wrapper = name)]`.
+//!
+//! The original function is left unchanged,
+//! and a second function with the same signature is generated which calls it inside an abort guard.
+//! This allows Rust callers to observe panics as usual,
+//! while an exported `extern "C"` entry point aborts.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::parse_quote;
+
+use crate::Args;
+
+pub(crate) fn with_wrapper(args: &Args, item: syn::ItemFn) -> TokenStream {
+    let name = args.wrapper.clone().expect("missing wrapper name");
+    let mut sig = item.sig.clone();
+    sig.ident = name;
+    if let Some(ref abi) = args.abi {
+        sig.abi = Some(parse_quote!(extern #abi));
+    }
+    let call_args = forward_inputs(&mut sig);
+    let original = &item.sig.ident;
+    // A function mentioning `Self` must be inside an impl block.
+    // Otherwise there is no way to tell, and an associated function fails to resolve.
+    // The error is reported at the name of the original function,
+    // where the compiler suggests using `Self::name`.
+    let in_impl =
+        item.sig.receiver().is_some() || contains_ident(item.sig.to_token_stream(), "Self");
+    let (func, doc) = if in_impl {
+        (
+            quote!(Self::#original),
+            format!(" Calls [`Self::{}`], aborting if it unwinds.", original),
+        )
+    } else {
+        (
+            quote_spanned!(original.span()=> #original),
+            format!(" Calls [`{}`], aborting if it unwinds.", original),
+        )
+    };
+    let turbofish = turbofish(&item.sig);
+    let await_suffix = sig.asyncness.map(|_| quote!(.await));
+    let mut block: syn::Block = parse_quote!({
+        #func #turbofish (#(#call_args),*) #await_suffix
+    });
+    let mut attrs = Vec::new();
+    attrs.push(parse_quote!(#[doc = #doc]));
+    if args.no_mangle {
+        attrs.push(parse_quote!(#[no_mangle]));
+    }
+    // track_caller requires the Rust ABI
+    if args.abi.is_none() {
+        attrs.extend(
+            item.attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("track_caller"))
+                .cloned(),
+        );
+    }
+    let unguarded = syn::ItemFn {
+        attrs: attrs.clone(),
+        vis: item.vis.clone(),
+        sig: sig.clone(),
+        block: Box::new(block.clone()),
+    };
+    let wrapper_args = Args {
+        wrapper: None,
+        abi: None,
+        no_mangle: false,
+        cfg: None,
+        ..args.clone()
+    };
+    crate::wrap_block(&wrapper_args, &attrs, &mut sig, &mut block);
+    let wrapper = syn::ItemFn {
+        attrs,
+        vis: item.vis.clone(),
+        sig,
+        block: Box::new(block),
+    };
+    match args.cfg {
+        // the original function is unchanged, so only the wrapper depends on the predicate
+        Some(ref cfg) => quote! {
+            #item
+            #[cfg(#cfg)]
+            #wrapper
+            #[cfg(not(#cfg))]
+            #unguarded
+        },
+        None => quote! {
+            #item
+            #wrapper
+        },
+    }
+}
+
+/// Replace the patterns in the signature with plain identifiers,
+/// returning the expressions which forward the arguments to the original function.
+///
+/// Simple identifiers are kept so the wrapper has readable documentation,
+/// while anything else is replaced by a generated name.
+fn forward_inputs(sig: &mut syn::Signature) -> Vec<TokenStream> {
+    let mut call_args = Vec::new();
+    for (index, input) in sig.inputs.iter_mut().enumerate() {
+        let typed = match input {
+            syn::FnArg::Receiver(receiver) => {
+                // the wrapper does not need to mutate the receiver
+                if receiver.reference.is_none() {
+                    receiver.mutability = None;
+                }
+                call_args.push(quote!(self));
+                continue;
+            }
+            syn::FnArg::Typed(typed) => typed,
+        };
+        let ident = match *typed.pat {
+            syn::Pat::Ident(ref pat) if pat.subpat.is_none() && pat.by_ref.is_none() => {
+                pat.ident.clone()
+            }
+            _ => syn::Ident::new(&format!("__arg{}", index), Span::call_site()),
+        };
+        *typed.pat = parse_quote!(#ident);
+        call_args.push(ident.into_token_stream());
+    }
+    call_args
+}
+
+/// Explicit generic arguments for calling the original function.
+///
+/// These cannot be specified if the function has `impl Trait` arguments,
+/// in which case they must be inferred.
+fn turbofish(sig: &syn::Signature) -> Option<TokenStream> {
+    let has_impl_trait = sig.inputs.iter().any(|input| match input {
+        syn::FnArg::Typed(typed) => contains_impl_trait(&typed.ty),
+        syn::FnArg::Receiver(_) => false,
+    });
+    if has_impl_trait {
+        return None;
+    }
+    let params = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            syn::GenericParam::Type(param) => Some(param.ident.clone()),
+            syn::GenericParam::Const(param) => Some(param.ident.clone()),
+            // late-bound lifetimes cannot be specified explicitly
+            syn::GenericParam::Lifetime(_) => None,
+        })
+        .collect::<Vec<_>>();
+    if params.is_empty() {
+        None
+    } else {
+        Some(quote!(::<#(#params),*>))
+    }
+}
+
+fn contains_impl_trait(ty: &syn::Type) -> bool {
+    contains_ident(ty.to_token_stream(), "impl")
+}
+
+/// Check if the tokens contain the specified identifier, including inside groups.
+fn contains_ident(tokens: TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == name,
+        proc_macro2::TokenTree::Group(group) => contains_ident(group.stream(), name),
+        _ => false,
+    })
+}
@@ -0,0 +1,223 @@
+//! Converting panics into error values at FFI boundaries.
+//!
+//! Sometimes aborting is too harsh,
+//! for example when a C API is expected to return an error code.
+//! The [`catch_to`] function and the `#[catch_unwind(return = value)]` attribute
+//! catch any panic and return a fallback value instead.
+//!
+//! The message and location of the caught panic are stored in a thread-local slot,
+//! which can be queried using [`last_error`] or [`take_last_error`],
+//! similar to `errno` in C.
+//! Successful calls do not clear the slot.
+//! C code can read it through [`format_last_error`].
+//!
+//! # Panic hook
+//! The location is recorded by a process-wide panic hook,
+//! which is installed by the first call to [`catch_to`]
+//! and calls the previously installed hook afterwards.
+//! Hooks installed later replace it, in which case the location is unavailable.
+//! Code which uses [`std::panic::take_hook`] will receive this hook rather than its own.
+//!
+//! [`std::panic::take_hook`]: https://doc.rust-lang.org/std/panic/fn.take_hook.html
+//!
+//! # Examples
+//! ```
+//! use nounwind::catch::{catch_to, take_last_error};
+//!
+//! #[no_mangle]
+//! pub extern "C" fn parse_port(value: u32) -> i32 {
+//!     catch_to(-1, || {
+//!         let port = u16::try_from(value).expect("port out of range");
+//!         i32::from(port)
+//!     })
+//! }
+//!
+//! assert_eq!(parse_port(80), 80);
+//! assert_eq!(parse_port(70_000), -1);
+//! let error = take_last_error().unwrap();
+//! assert!(error.message().starts_with("port out of range"));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::Once;
+
+thread_local! {
+    /// The error from the most recent panic caught by [`catch_to`].
+    static LAST_ERROR: RefCell<Option<LastError>> = RefCell::new(None);
+    /// The number of active [`catch_to`] calls on this thread.
+    static DEPTH: Cell<usize> = Cell::new(0);
+    /// The location recorded by the panic hook, if inside [`catch_to`].
+    static PENDING_LOCATION: RefCell<Option<(String, u32, u32)>> = RefCell::new(None);
+}
+
+/// Invoke the closure, returning the specified default value if it panics.
+///
+/// The message and location of the panic are stored,
+/// and can be retrieved using [`last_error`] or [`take_last_error`].
+/// The panic hook still runs as usual, so the message is printed to stderr by default.
+///
+/// Unwind safety is not enforced, as is usual at FFI boundaries.
+/// See [`std::panic::catch_unwind`] for the implications.
+///
+/// # Panic hook
+/// The first call permanently installs a process-wide panic hook
+/// to record the location of the panic, wrapping the existing one.
+/// It is never removed, so it affects every later panic in the process,
+/// including those on other threads and outside of `catch_to`.
+/// Those are passed straight to the previous hook.
+/// See the [module documentation](self#panic-hook) for details.
+///
+/// [`std::panic::catch_unwind`]: https://doc.rust-lang.org/std/panic/fn.catch_unwind.html
+#[track_caller]
+pub fn catch_to<F: FnOnce() -> R, R>(default: R, func: F) -> R {
+    let location = Location::caller();
+    install_hook();
+    // Discard any location left by a panic that was caught elsewhere.
+    // An enclosing call may be unwinding through a destructor which calls this,
+    // so its location is restored afterwards.
+    let outer = PENDING_LOCATION.with(|pending| pending.borrow_mut().take());
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let res = panic::catch_unwind(AssertUnwindSafe(func));
+    DEPTH.with(|depth| depth.set(depth.get() - 1));
+    let pending = PENDING_LOCATION.with(|pending| pending.replace(outer));
+    match res {
+        Ok(res) => res,
+        Err(payload) => {
+            let error = LastError {
+                message: crate::crash_report::payload_message(&*payload),
+                location: pending,
+            };
+            // dropping the payload could panic
+            crate::abort_unwind_at(location, move || drop(payload));
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+            default
+        }
+    }
+}
+
+/// The error from the most recent panic caught on this thread, if any.
+pub fn last_error() -> Option<LastError> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+/// Take the error from the most recent panic caught on this thread,
+/// leaving the slot empty.
+pub fn take_last_error() -> Option<LastError> {
+    LAST_ERROR.with(|last| last.borrow_mut().take())
+}
+
+/// Copy the error from the most recent panic caught on this thread into a C string,
+/// for callers which cannot use [`last_error`].
+///
+/// The error is formatted like the [`Display`](fmt::Display) implementation of [`LastError`],
+/// and the slot is left unchanged.
+/// At most `len - 1` bytes are written to `buf`, followed by a nul terminator.
+/// A message containing a nul byte is therefore cut short when read from C.
+///
+/// Returns the length of the whole error in bytes, excluding the nul terminator,
+/// or -1 if there is no error.
+/// Like `snprintf`, a result of `len` or more means the error was truncated,
+/// and calling this with a null `buf` and a `len` of 0 queries the required size.
+///
+/// This is not exported with `#[no_mangle]`, as a library should not claim the symbol name.
+/// Export it under a name of your choosing by wrapping it, or pass it to C as a function pointer.
+///
+/// # Safety
+/// Unless `len` is 0, `buf` must be valid for writes of `len` bytes.
+///
+/// # Examples
+/// ```
+/// use nounwind::catch::{catch_to, format_last_error};
+/// use std::ffi::CStr;
+///
+/// catch_to((), || panic!("invalid handle"));
+/// let mut buf = [0; 64];
+/// let len = unsafe { format_last_error(buf.as_mut_ptr(), buf.len()) };
+/// let error = unsafe { CStr::from_ptr(buf.as_ptr()) };
+/// assert!(error.to_str().unwrap().starts_with("invalid handle at "));
+/// assert_eq!(len, error.to_bytes().len() as isize);
+/// ```
+pub unsafe extern "C" fn format_last_error(buf: *mut c_char, len: usize) -> isize {
+    // formatting allocates, which must not unwind into C
+    let error = crate::abort_unwind(|| last_error().map(|error| error.to_string()));
+    let error = match error {
+        Some(error) => error,
+        None => return -1,
+    };
+    if len > 0 {
+        let copied = error.len().min(len - 1);
+        // SAFETY: The caller guarantees `buf` is valid for `len` bytes,
+        // and `copied` is less than `len`.
+        std::ptr::copy_nonoverlapping(error.as_ptr().cast::<c_char>(), buf, copied);
+        *buf.add(copied) = 0;
+    }
+    error.len() as isize
+}
+
+/// A panic caught by [`catch_to`] or `#[catch_unwind]`.
+#[derive(Clone, Debug)]
+pub struct LastError {
+    message: String,
+    location: Option<(String, u32, u32)>,
+}
+impl LastError {
+    /// The message of the panic.
+    ///
+    /// If the payload was not a string, this is `Box<dyn Any>` like the stdlib.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The file where the panic occurred, if known.
+    #[inline]
+    pub fn file(&self) -> Option<&str> {
+        self.location.as_ref().map(|(file, _, _)| &**file)
+    }
+
+    /// The line where the panic occurred, if known.
+    #[inline]
+    pub fn line(&self) -> Option<u32> {
+        self.location.as_ref().map(|&(_, line, _)| line)
+    }
+
+    /// The column where the panic occurred, if known.
+    #[inline]
+    pub fn column(&self) -> Option<u32> {
+        self.location.as_ref().map(|&(_, _, column)| column)
+    }
+}
+impl fmt::Display for LastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some((ref file, line, column)) = self.location {
+            write!(f, " at {}:{}:{}", file, line, column)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for LastError {}
+
+/// Install a panic hook recording the location of panics inside [`catch_to`],
+/// which then calls the previous hook.
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    if std::thread::panicking() {
+        // the hook cannot be changed while panicking
+        return;
+    }
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let inside = DEPTH.try_with(|depth| depth.get() > 0).unwrap_or(false);
+            if let (true, Some(location)) = (inside, info.location()) {
+                let location = (location.file().into(), location.line(), location.column());
+                let _ = PENDING_LOCATION.try_with(|pending| *pending.borrow_mut() = Some(location));
+            }
+            previous(info);
+        }));
+    });
+}
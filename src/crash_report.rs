@@ -0,0 +1,354 @@
+//! Writes a machine-readable crash report before aborting.
+//!
+//! The report is only written if the [`ENV_VAR`] environment variable is set,
+//! to either a file path or `fd:N` for an inherited file descriptor.
+//! It is a single line of JSON, described in the crate documentation.
+//! Reports are appended rather than truncating the file,
+//! as child processes inherit the variable and may report to the same file.
+//!
+//! The details of the panic which caused the abort are recorded by a panic hook,
+//! since the location and backtrace of the panic are gone once it unwinds.
+//! The hook is installed by [`prepare`] if the variable is set.
+// The backtrace is only captured on Rust 1.65 and later
+#![cfg_attr(nounwind_std_backtrace, allow(clippy::incompatible_msrv))]
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Write as _};
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The environment variable naming the destination of the report.
+const ENV_VAR: &str = "NOUNWIND_CRASH_REPORT";
+
+const UNKNOWN: u8 = 0;
+const DISABLED: u8 = 1;
+const ENABLED: u8 = 2;
+
+/// Whether crash reports are enabled, determined by the first call to [`prepare`].
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Held while writing a report, so reports from several threads are not interleaved.
+///
+/// `Mutex::new` is not `const` before Rust 1.63, so this is a spin lock.
+static WRITING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The most recent panic on this thread, recorded by the panic hook.
+    static LAST_PANIC: RefCell<Option<PanicRecord>> = RefCell::new(None);
+}
+
+/// The details of a panic, recorded by the panic hook when it occurs.
+struct PanicRecord {
+    message: String,
+    location: Option<(String, u32, u32)>,
+    #[cfg(nounwind_std_backtrace)]
+    backtrace: std::backtrace::Backtrace,
+}
+
+/// The panic which caused the program to abort.
+pub(crate) enum Cause<'a> {
+    /// There was no panic, for example when calling `panic_nounwind!`.
+    None,
+    /// A panic is unwinding, and its details were recorded by the panic hook.
+    Unwinding,
+    /// A panic was caught, with the specified payload.
+    Caught(&'a (dyn Any + Send)),
+}
+
+/// Prepare to report a panic from code which cannot unwind.
+///
+/// If crash reports are enabled, this installs the panic hook on first use,
+/// and discards any panic recorded earlier which was caught elsewhere.
+#[inline]
+pub(crate) fn prepare() {
+    if STATE.load(Ordering::Relaxed) != DISABLED {
+        prepare_slow();
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn prepare_slow() {
+    // The hook cannot be installed while panicking,
+    // and an enclosing section may be unwinding through a destructor which called this.
+    if std::thread::panicking() {
+        return;
+    }
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let enabled = destination().is_some();
+        if enabled {
+            install_hook();
+        }
+        STATE.store(if enabled { ENABLED } else { DISABLED }, Ordering::Release);
+    });
+    if STATE.load(Ordering::Acquire) == ENABLED {
+        let _ = LAST_PANIC.try_with(|last| last.borrow_mut().take());
+    }
+}
+
+/// Install a panic hook which records every panic,
+/// and then calls the previous hook.
+fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let record = PanicRecord {
+            message: payload_message(info.payload()),
+            location: info
+                .location()
+                .map(|location| (location.file().into(), location.line(), location.column())),
+            #[cfg(nounwind_std_backtrace)]
+            backtrace: std::backtrace::Backtrace::force_capture(),
+        };
+        let _ = LAST_PANIC.try_with(|last| {
+            if let Ok(mut last) = last.try_borrow_mut() {
+                *last = Some(record);
+            }
+        });
+        previous(info);
+    }));
+}
+
+/// The destination of the report, if the [`ENV_VAR`] environment variable is set.
+fn destination() -> Option<OsString> {
+    std::env::var_os(ENV_VAR).filter(|dest| !dest.is_empty())
+}
+
+/// Write a crash report, if the [`ENV_VAR`] environment variable is set.
+///
+/// Errors are ignored, as we are about to abort anyways.
+#[cold]
+#[inline(never)]
+pub(crate) fn write(
+    message: fmt::Arguments<'_>,
+    location: Option<&Location<'_>>,
+    cause: Cause<'_>,
+) {
+    let dest = match destination() {
+        Some(dest) => dest,
+        None => return,
+    };
+    // Building the report could panic, for example if the thread is being destroyed
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let record = match cause {
+            Cause::None => None,
+            Cause::Unwinding | Cause::Caught(_) => LAST_PANIC
+                .try_with(|last| last.borrow_mut().take())
+                .ok()
+                .flatten(),
+        };
+        let mut report = String::new();
+        let _ = build(&mut report, message, location, &cause, record.as_ref());
+        report.push('\n');
+        write_to(&dest, report.as_bytes());
+    }));
+}
+
+fn build(
+    out: &mut String,
+    message: fmt::Arguments<'_>,
+    location: Option<&Location<'_>>,
+    cause: &Cause<'_>,
+    record: Option<&PanicRecord>,
+) -> fmt::Result {
+    out.push_str("{\"message\":");
+    write_str(out, &message.to_string())?;
+    out.push_str(",\"location\":");
+    match location {
+        Some(location) => write_location(out, location)?,
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"panic\":");
+    match *cause {
+        Cause::None => out.push_str("null"),
+        Cause::Unwinding | Cause::Caught(_) => {
+            out.push_str("{\"message\":");
+            match (cause, record) {
+                // the payload is more reliable, as the hook could have been replaced
+                (&Cause::Caught(payload), _) => write_str(out, &payload_message(payload))?,
+                (_, Some(record)) => write_str(out, &record.message)?,
+                (_, None) => out.push_str("null"),
+            }
+            out.push_str(",\"location\":");
+            match record.and_then(|record| record.location.as_ref()) {
+                Some(&(ref file, line, column)) => write_location_parts(out, file, line, column)?,
+                None => out.push_str("null"),
+            }
+            out.push('}');
+        }
+    }
+    let thread = std::thread::current();
+    out.push_str(",\"thread\":{\"name\":");
+    match thread.name() {
+        Some(name) => write_str(out, name)?,
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"id\":");
+    match thread_id(thread.id()) {
+        Some(id) => write!(out, "{}", id)?,
+        None => out.push_str("null"),
+    }
+    write!(out, "}},\"pid\":{}", std::process::id())?;
+    out.push_str(",\"timestamp_ms\":");
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => write!(out, "{}", time.as_millis())?,
+        Err(_) => out.push_str("null"),
+    }
+    out.push_str(",\"sections\":[");
+    let mut first = true;
+    let mut res = Ok(());
+    crate::sections::for_each_location(|location| {
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        res = res.and_then(|()| write_location(out, location));
+    });
+    res?;
+    out.push_str("],\"backtrace\":");
+    #[cfg(nounwind_std_backtrace)]
+    {
+        // prefer the backtrace of the panic, which has not unwound yet
+        let rendered = match record {
+            Some(record) => record.backtrace.to_string(),
+            None => std::backtrace::Backtrace::force_capture().to_string(),
+        };
+        write_str(out, trim_backtrace(&rendered))?;
+    }
+    #[cfg(not(nounwind_std_backtrace))]
+    {
+        let _ = record;
+        out.push_str("null");
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// The prefixes of the symbols belonging to the panic machinery or this crate.
+#[cfg(nounwind_std_backtrace)]
+const INTERNAL_SYMBOLS: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+    "nounwind::",
+    "<nounwind::",
+    "rust_begin_unwind",
+    "__rust",
+];
+
+/// Skip the frames at the top of a rendered backtrace,
+/// which belong to the panic machinery or this crate.
+///
+/// [`std::backtrace::Backtrace::frames`] is unstable,
+/// so this works on the rendered form.
+#[cfg(nounwind_std_backtrace)]
+fn trim_backtrace(rendered: &str) -> &str {
+    let mut offset = 0;
+    for line in rendered.split_inclusive('\n') {
+        // frames start with their index, followed by the symbol
+        let symbol = line
+            .trim_start()
+            .split_once(": ")
+            .filter(|(index, _)| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+            .map(|(_, symbol)| symbol);
+        if let Some(symbol) = symbol {
+            if !INTERNAL_SYMBOLS
+                .iter()
+                .any(|prefix| symbol.starts_with(prefix))
+            {
+                return &rendered[offset..];
+            }
+        }
+        offset += line.len();
+    }
+    // every frame is internal, or the backtrace is unavailable
+    rendered
+}
+
+fn write_location(out: &mut String, location: &Location<'_>) -> fmt::Result {
+    write_location_parts(out, location.file(), location.line(), location.column())
+}
+
+fn write_location_parts(out: &mut String, file: &str, line: u32, column: u32) -> fmt::Result {
+    out.push_str("{\"file\":");
+    write_str(out, file)?;
+    write!(out, ",\"line\":{},\"column\":{}}}", line, column)
+}
+
+/// Write a JSON string literal.
+fn write_str(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+/// The message of a panic payload, or `Box<dyn Any>` if it is not a string.
+pub(crate) fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        (*msg).into()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Box<dyn Any>".into()
+    }
+}
+
+/// The numeric id of the thread.
+///
+/// [`std::thread::ThreadId::as_u64`] is unstable,
+/// so this is parsed from the `Debug` representation.
+fn thread_id(id: std::thread::ThreadId) -> Option<u64> {
+    let debug = format!("{:?}", id);
+    let digits = debug.trim_start_matches("ThreadId(").trim_end_matches(')');
+    digits.parse().ok()
+}
+
+fn write_to(dest: &OsStr, report: &[u8]) {
+    while WRITING
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        std::thread::yield_now();
+    }
+    write_unlocked(dest, report);
+    WRITING.store(false, Ordering::Release);
+}
+
+fn write_unlocked(dest: &OsStr, report: &[u8]) {
+    if let Some(fd) = dest.to_str().and_then(|dest| dest.strip_prefix("fd:")) {
+        #[cfg(unix)]
+        if let Ok(fd) = fd.trim().parse::<std::os::unix::io::RawFd>() {
+            use std::os::unix::io::FromRawFd;
+            // SAFETY: The descriptor was inherited for this purpose,
+            // and is not closed because the file is never dropped
+            let file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+            let _ = (&*file).write_all(report);
+        }
+        #[cfg(not(unix))]
+        let _ = fd;
+        return;
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(dest) {
+        let _ = file.write_all(report);
+    }
+}
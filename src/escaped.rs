@@ -0,0 +1,64 @@
+use core::any::Any;
+use core::panic::Location;
+
+/// Information about a panic that attempted to unwind out of a `#[nounwind]` function.
+///
+/// This is passed to the handler given by `#[nounwind(on_panic = handler)]`,
+/// which runs just before the program aborts.
+///
+/// # Examples
+/// A handler which can be used as `#[nounwind(on_panic = report)]`:
+/// ```
+/// fn report(panic: &nounwind::EscapedPanic<'_>) {
+///     eprintln!(
+///         "ffi boundary at {} crossed: {}",
+///         panic.location(),
+///         panic.message().unwrap_or("<unknown>"),
+///     );
+/// }
+/// ```
+#[derive(Copy, Clone)]
+pub struct EscapedPanic<'a> {
+    pub(crate) payload: Option<&'a (dyn Any + Send)>,
+    pub(crate) location: &'static Location<'static>,
+}
+impl<'a> EscapedPanic<'a> {
+    /// The payload of the panic.
+    ///
+    /// This requires the `std` feature,
+    /// as catching the payload requires [`std::panic::catch_unwind`].
+    /// Even then, it is `None` for a `#[track_caller]` or `const fn`,
+    /// whose body cannot be run inside `catch_unwind`
+    /// and is guarded by a value which aborts when dropped instead.
+    ///
+    /// [`std::panic::catch_unwind`]: https://doc.rust-lang.org/std/panic/fn.catch_unwind.html
+    #[inline]
+    pub fn payload(&self) -> Option<&'a (dyn Any + Send)> {
+        self.payload
+    }
+
+    /// The message of the panic, if the payload is a string.
+    ///
+    /// This is the case for any panic created by [`core::panic!`],
+    /// as long as the [payload](Self::payload) is available.
+    pub fn message(&self) -> Option<&'a str> {
+        let payload = self.payload?;
+        if let Some(msg) = payload.downcast_ref::<&'static str>() {
+            return Some(msg);
+        }
+        #[cfg(feature = "std")]
+        if let Some(msg) = payload.downcast_ref::<String>() {
+            return Some(msg);
+        }
+        None
+    }
+
+    /// The location of the `#[nounwind]` function the panic attempted to escape.
+    ///
+    /// This is where the function is defined, not where the panic happened.
+    /// The location of the panic itself is printed by the panic handler as usual.
+    #[inline]
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+}
@@ -0,0 +1,131 @@
+//! Extension traits for unwrapping [`Option`] and [`Result`] without unwinding.
+//!
+//! These behave like the standard `unwrap` and `expect` methods,
+//! and print the same messages including the `Debug` output of the error,
+//! but are guaranteed to abort instead of unwinding like [`crate::panic_nounwind!`].
+//!
+//! # Examples
+//! ```
+//! use nounwind::{OptionExt, ResultExt};
+//!
+//! let index = [1, 2, 3].iter().position(|&x| x == 2).unwrap_nounwind();
+//! let value: u8 = "42".parse().expect_nounwind("invalid value");
+//! assert_eq!((index, value), (1, 42));
+//! ```
+
+use core::fmt;
+
+mod sealed {
+    pub trait Sealed {}
+    impl<T> Sealed for Option<T> {}
+    impl<T, E> Sealed for Result<T, E> {}
+}
+
+/// Extension methods for [`Option`], which abort instead of unwinding.
+///
+/// This trait is sealed, and cannot be implemented outside this crate.
+pub trait OptionExt<T>: sealed::Sealed {
+    /// Equivalent to [`Option::unwrap`], but guaranteed to abort instead of unwinding.
+    #[track_caller]
+    fn unwrap_nounwind(self) -> T;
+
+    /// Equivalent to [`Option::expect`], but guaranteed to abort instead of unwinding.
+    #[track_caller]
+    fn expect_nounwind(self, msg: &str) -> T;
+}
+impl<T> OptionExt<T> for Option<T> {
+    #[inline]
+    #[track_caller]
+    fn unwrap_nounwind(self) -> T {
+        match self {
+            Some(value) => value,
+            None => crate::panic_nounwind("called `Option::unwrap()` on a `None` value"),
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn expect_nounwind(self, msg: &str) -> T {
+        match self {
+            Some(value) => value,
+            None => expect_failed(msg),
+        }
+    }
+}
+
+/// Extension methods for [`Result`], which abort instead of unwinding.
+///
+/// This includes `std::thread::Result`,
+/// where the error is printed as `Any { .. }` just like the standard methods.
+///
+/// This trait is sealed, and cannot be implemented outside this crate.
+pub trait ResultExt<T, E>: sealed::Sealed {
+    /// Equivalent to [`Result::unwrap`], but guaranteed to abort instead of unwinding.
+    #[track_caller]
+    fn unwrap_nounwind(self) -> T
+    where
+        E: fmt::Debug;
+
+    /// Equivalent to [`Result::expect`], but guaranteed to abort instead of unwinding.
+    #[track_caller]
+    fn expect_nounwind(self, msg: &str) -> T
+    where
+        E: fmt::Debug;
+
+    /// Equivalent to [`Result::unwrap_err`], but guaranteed to abort instead of unwinding.
+    #[track_caller]
+    fn unwrap_err_nounwind(self) -> E
+    where
+        T: fmt::Debug;
+}
+impl<T, E> ResultExt<T, E> for Result<T, E> {
+    #[inline]
+    #[track_caller]
+    fn unwrap_nounwind(self) -> T
+    where
+        E: fmt::Debug,
+    {
+        match self {
+            Ok(value) => value,
+            Err(error) => unwrap_failed("called `Result::unwrap()` on an `Err` value", &error),
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn expect_nounwind(self, msg: &str) -> T
+    where
+        E: fmt::Debug,
+    {
+        match self {
+            Ok(value) => value,
+            Err(error) => unwrap_failed(msg, &error),
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn unwrap_err_nounwind(self) -> E
+    where
+        T: fmt::Debug,
+    {
+        match self {
+            Ok(value) => unwrap_failed("called `Result::unwrap_err()` on an `Ok` value", &value),
+            Err(error) => error,
+        }
+    }
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn expect_failed(msg: &str) -> ! {
+    crate::panic_nounwind!("{}", msg)
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+fn unwrap_failed(msg: &str, value: &dyn fmt::Debug) -> ! {
+    crate::panic_nounwind!("{}: {:?}", msg, value)
+}
@@ -0,0 +1,153 @@
+//! Adapters that abort if polling a [`Future`] or stream unwinds.
+//!
+//! These work with any executor,
+//! and can be used to make a panic in a spawned task fatal without needing the `#[nounwind]` macro.
+//!
+//! # Examples
+//! ```
+//! use nounwind::future::FutureExt as _;
+//!
+//! async fn work() -> u32 {
+//!     7
+//! }
+//! let _guarded = work().abort_on_unwind();
+//! ```
+
+use core::future::Future;
+use core::mem::ManuallyDrop;
+use core::panic::Location;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::panic_internals::NounwindFn;
+
+/// Wraps a future or stream, aborting if polling it unwinds.
+///
+/// Every call to [`Future::poll`] runs inside [`crate::abort_unwind`],
+/// so a panic anywhere in the body of an `async` block will abort.
+/// The abort message reports where the wrapper was created.
+/// Dropping the wrapped value is guarded as well.
+///
+/// When the `futures-core` feature is enabled,
+/// this also implements [`Stream`] if the wrapped value does.
+///
+/// This is the implementation of `#[nounwind] async fn`.
+///
+/// [`Stream`]: https://docs.rs/futures-core/0.3/futures_core/stream/trait.Stream.html
+#[must_use = "futures do nothing unless polled"]
+pub struct AbortOnUnwind<F> {
+    inner: ManuallyDrop<F>,
+    /// The configuration of a `#[nounwind(...)]` async function.
+    info: Option<NounwindFn>,
+    /// Where the wrapper was created, reported if it aborts.
+    location: &'static Location<'static>,
+}
+impl<F> AbortOnUnwind<F> {
+    /// Wrap the specified future or stream.
+    #[inline]
+    #[track_caller]
+    pub fn new(inner: F) -> Self {
+        AbortOnUnwind {
+            inner: ManuallyDrop::new(inner),
+            info: None,
+            location: Location::caller(),
+        }
+    }
+
+    /// Wrap the body of a `#[nounwind(...)]` async function.
+    ///
+    /// This is an implementation detail of the `#[nounwind]` macro.
+    #[doc(hidden)]
+    #[inline]
+    #[track_caller]
+    pub fn with_info(inner: F, info: NounwindFn) -> Self {
+        AbortOnUnwind {
+            inner: ManuallyDrop::new(inner),
+            info: Some(info),
+            location: Location::caller(),
+        }
+    }
+
+    /// Invoke the specified function on the pinned inner value, aborting if it unwinds.
+    #[inline]
+    fn guard_pinned<R>(self: Pin<&mut Self>, func: impl FnOnce(Pin<&mut F>) -> R) -> R {
+        // SAFETY: The inner value is structurally pinned and never moved
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut *this.inner) };
+        guard(&this.info, this.location, move || func(inner))
+    }
+}
+
+/// Invoke the specified function, aborting if it unwinds.
+#[inline]
+fn guard<R>(
+    info: &Option<NounwindFn>,
+    location: &'static Location<'static>,
+    func: impl FnOnce() -> R,
+) -> R {
+    match *info {
+        None => crate::abort_unwind_at(location, func),
+        Some(ref info) => crate::panic_internals::call_nounwind(info, func),
+    }
+}
+impl<F: Future> Future for AbortOnUnwind<F> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        self.guard_pinned(|inner| inner.poll(cx))
+    }
+}
+#[cfg(feature = "futures-core")]
+impl<S: futures_core::Stream> futures_core::Stream for AbortOnUnwind<S> {
+    type Item = S::Item;
+
+    #[inline]
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        self.guard_pinned(|inner| inner.poll_next(cx))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        guard(&self.info, self.location, || self.inner.size_hint())
+    }
+}
+impl<F> Drop for AbortOnUnwind<F> {
+    #[inline]
+    fn drop(&mut self) {
+        let inner = &mut self.inner;
+        // SAFETY: The inner value is dropped in place exactly once
+        guard(&self.info, self.location, || unsafe {
+            ManuallyDrop::drop(inner)
+        })
+    }
+}
+
+/// An extension trait to wrap a [`Future`] in [`AbortOnUnwind`].
+pub trait FutureExt: Future + Sized {
+    /// Abort the program if polling this future unwinds.
+    ///
+    /// See [`AbortOnUnwind`] for details.
+    #[inline]
+    #[track_caller]
+    fn abort_on_unwind(self) -> AbortOnUnwind<Self> {
+        AbortOnUnwind::new(self)
+    }
+}
+impl<F: Future> FutureExt for F {}
+
+/// An extension trait to wrap a [`Stream`](futures_core::Stream) in [`AbortOnUnwind`].
+#[cfg(feature = "futures-core")]
+#[cfg_attr(docsrs, doc(cfg(feature = "futures-core")))]
+pub trait StreamExt: futures_core::Stream + Sized {
+    /// Abort the program if polling this stream unwinds.
+    ///
+    /// See [`AbortOnUnwind`] for details.
+    #[inline]
+    #[track_caller]
+    fn abort_on_unwind(self) -> AbortOnUnwind<Self> {
+        AbortOnUnwind::new(self)
+    }
+}
+#[cfg(feature = "futures-core")]
+impl<S: futures_core::Stream> StreamExt for S {}
@@ -0,0 +1,135 @@
+use core::fmt;
+use core::panic::Location;
+
+/// A guard which aborts the program if it is dropped without being defused.
+///
+/// This is a "drop bomb", useful for code which must not be interrupted
+/// across several statements or early returns,
+/// where wrapping the code in a closure passed to [`crate::abort_unwind`] is not practical.
+/// Once the critical section is complete, call [`AbortGuard::defuse`].
+///
+/// Before aborting, the message and location of the guard are printed.
+/// With the `std` feature, these are written to stderr.
+/// Otherwise, they are given to the panic handler, which is not allowed to unwind.
+///
+/// # Examples
+/// ```
+/// use nounwind::AbortGuard;
+///
+/// fn transfer(from: &mut Vec<u32>, to: &mut Vec<u32>) -> Option<()> {
+///     let guard = AbortGuard::new("transfer interrupted, lists are inconsistent");
+///     let value = match from.pop() {
+///         Some(value) => value,
+///         None => {
+///             guard.defuse();
+///             return None;
+///         }
+///     };
+///     to.push(value);
+///     guard.defuse();
+///     Some(())
+/// }
+/// let mut from = vec![1, 2];
+/// let mut to = Vec::new();
+/// assert_eq!(transfer(&mut from, &mut to), Some(()));
+/// assert_eq!(to, [2]);
+/// ```
+#[must_use = "dropping an AbortGuard immediately aborts the program"]
+pub struct AbortGuard {
+    message: Option<&'static str>,
+    location: Option<&'static Location<'static>>,
+}
+impl AbortGuard {
+    /// A guard which aborts without printing anything,
+    /// used to implement the rest of the crate.
+    pub(crate) const SILENT: AbortGuard = AbortGuard {
+        message: None,
+        location: None,
+    };
+
+    /// Create a guard which prints the specified message before aborting.
+    ///
+    /// The location of the caller is printed as well.
+    #[inline]
+    #[track_caller]
+    pub fn new(message: &'static str) -> Self {
+        #[cfg(feature = "std")]
+        crate::crash_report::prepare();
+        AbortGuard {
+            message: Some(message),
+            location: Some(Location::caller()),
+        }
+    }
+
+    /// Create a guard which prints the specified location before aborting.
+    #[inline]
+    pub const fn at(location: &'static Location<'static>) -> Self {
+        AbortGuard {
+            message: None,
+            location: Some(location),
+        }
+    }
+
+    /// Disarm the guard, so that it no longer aborts.
+    #[inline]
+    pub const fn defuse(self) {
+        core::mem::forget(self);
+    }
+}
+impl AbortGuard {
+    #[inline]
+    fn message(&self) -> &'static str {
+        self.message
+            .unwrap_or("abort guard dropped without being defused")
+    }
+}
+impl Drop for AbortGuard {
+    #[cold]
+    fn drop(&mut self) {
+        if self.message.is_none() && self.location.is_none() {
+            abort()
+        }
+        #[cfg(feature = "std")]
+        crate::crash_report::write(
+            format_args!("{}", self.message()),
+            self.location,
+            if std::thread::panicking() {
+                crate::crash_report::Cause::Unwinding
+            } else {
+                crate::crash_report::Cause::None
+            },
+        );
+        if crate::sink::print_fatal(format_args!("{}", self), None) {
+            abort()
+        }
+        crate::panic_nounwind!("{}", self)
+    }
+}
+impl fmt::Display for AbortGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())?;
+        if let Some(location) = self.location {
+            write!(f, " (guard created at {})", location)?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Debug for AbortGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortGuard")
+            .field("message", &self.message)
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+/// Abort the program immediately, without printing anything.
+///
+/// The [hooks](crate::hooks) are run first,
+/// and then the program is terminated using the current [strategy](crate::terminate).
+#[cold]
+#[inline(never)]
+pub(crate) fn abort() -> ! {
+    crate::hooks::run();
+    crate::terminate::terminate()
+}
@@ -0,0 +1,131 @@
+//! Callbacks which run before the program aborts.
+//!
+//! Hooks are useful for flushing logs and buffered output,
+//! syncing a write-ahead log, or notifying a supervisor process.
+//! They run whenever this crate aborts the program,
+//! including when a panic escapes [`crate::abort_unwind`] or a `#[nounwind]` function,
+//! when an [`AbortGuard`](crate::AbortGuard) is dropped,
+//! and after the message of [`crate::panic_nounwind!`] is printed.
+//!
+//! Hooks run in the order they were registered, at most [`MAX_HOOKS`] of them.
+//! They only ever run once, so if a hook triggers another abort,
+//! the remaining hooks are skipped rather than running recursively.
+//! With the `std` feature, a panicking hook is caught and the next hook runs,
+//! and a watchdog thread aborts the program if the hooks take longer than `TIMEOUT`.
+//! However, older versions of Rust cannot catch a panic while the thread is already unwinding,
+//! so if the hooks run during unwinding, a panicking hook aborts immediately.
+//! Without the stdlib, a panicking hook aborts immediately, skipping the remaining hooks.
+//!
+//! # Examples
+//! ```
+//! fn flush_logs() {
+//!     use std::io::Write;
+//!     let _ = std::io::stdout().flush();
+//! }
+//! nounwind::hooks::register(flush_logs).unwrap();
+//! ```
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The maximum number of hooks which can be registered.
+pub const MAX_HOOKS: usize = 16;
+
+/// The maximum time hooks can run for before the program is aborted anyways.
+///
+/// This is only enforced with the `std` feature.
+#[cfg(feature = "std")]
+pub const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The registered hooks, stored as `fn()` pointers cast to `usize`.
+///
+/// Zero indicates a slot which has been reserved, but not yet written.
+#[allow(clippy::declare_interior_mutable_const)]
+static HOOKS: [AtomicUsize; MAX_HOOKS] = {
+    const EMPTY: AtomicUsize = AtomicUsize::new(0);
+    [EMPTY; MAX_HOOKS]
+};
+/// The number of reserved slots in [`HOOKS`].
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Set once the hooks start running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Register a hook to run before the program aborts.
+///
+/// Returns an error if [`MAX_HOOKS`] hooks have already been registered.
+pub fn register(hook: fn()) -> Result<(), TooManyHooks> {
+    let index = COUNT.fetch_add(1, Ordering::AcqRel);
+    if index >= MAX_HOOKS {
+        COUNT.fetch_sub(1, Ordering::AcqRel);
+        return Err(TooManyHooks);
+    }
+    HOOKS[index].store(hook as usize, Ordering::Release);
+    Ok(())
+}
+
+/// The error returned by [`register`] when [`MAX_HOOKS`] hooks have already been registered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TooManyHooks;
+impl fmt::Display for TooManyHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot register more than {} abort hooks", MAX_HOOKS)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for TooManyHooks {}
+
+/// Run the registered hooks, unless they are already running.
+#[cold]
+#[inline(never)]
+pub(crate) fn run() {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let count = COUNT.load(Ordering::Acquire).min(MAX_HOOKS);
+    if count == 0 {
+        return;
+    }
+    #[cfg(feature = "std")]
+    start_watchdog();
+    for slot in &HOOKS[..count] {
+        let hook = slot.load(Ordering::Acquire);
+        if hook == 0 {
+            continue;
+        }
+        // SAFETY: Nonzero values were created from a `fn()` in `register`
+        let hook = unsafe { core::mem::transmute::<usize, fn()>(hook) };
+        #[cfg(feature = "std")]
+        {
+            let _ = std::panic::catch_unwind(hook);
+        }
+        // a panic must never unwind out of the abort
+        #[cfg(not(feature = "std"))]
+        crate::abort_unwind_raw(hook);
+    }
+}
+
+/// Abort the program if the hooks take longer than [`TIMEOUT`].
+#[cfg(feature = "std")]
+fn start_watchdog() {
+    // If the thread cannot be spawned, run the hooks without a timeout
+    let _ = std::thread::Builder::new()
+        .name("nounwind-hooks-watchdog".into())
+        .spawn(|| {
+            std::thread::sleep(TIMEOUT);
+            std::process::abort()
+        });
+}
+
+/// Runs the hooks when dropped.
+///
+/// Used where the abort is performed by the compiler,
+/// and the crate only observes the unwinding.
+#[cfg(all(nounwind_extern_c_will_abort, not(feature = "std")))]
+pub(crate) struct RunOnDrop;
+#[cfg(all(nounwind_extern_c_will_abort, not(feature = "std")))]
+impl Drop for RunOnDrop {
+    #[inline]
+    fn drop(&mut self) {
+        run();
+    }
+}
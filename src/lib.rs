@@ -7,24 +7,108 @@
 //! [`panic_nounwind!`] should be used instead of [`core::panic!`].
 //! Similar [`assert_nounwind!`] and [`unreachable_nounwind!`] macros are offered,
 //! which are convenience wrappers around [`panic_nounwind!`].
+//! The [`assert_eq_nounwind!`], [`assert_ne_nounwind!`], and [`assert_matches_nounwind!`] macros
+//! print the `Debug` output of the values just like the stdlib,
+//! and each assertion has a `debug_` variant.
+//! The [`OptionExt`] and [`ResultExt`] traits provide `unwrap_nounwind` and `expect_nounwind` methods.
+//! The [`panic_nounwind_force_backtrace!`] and [`panic_nounwind_nobacktrace!`] variants
+//! always or never print a backtrace, regardless of `RUST_BACKTRACE`.
 //!
 //! The crate also provides a polyfill for the nightly [`std::panic::abort_unwind`] function.
 //! This provides more detailed control over what sections of code can and cannot panic.
 //! It can also be used as a replacement to `#[nounwind]` if you want to avoid a macro dependency.
+//! The [`AbortOnUnwind`] adapter does the same for futures,
+//! aborting if any call to `poll` unwinds.
+//! For code that cannot easily be wrapped in a closure,
+//! [`AbortGuard`] aborts if it is dropped without being defused,
+//! and `UnwindGuard` aborts only if it is dropped during unwinding.
+//!
+//! Where aborting is too harsh, such as a C API that reports errors using return codes,
+//! the `catch_to` function and `#[catch_unwind(return = value)]` attribute
+//! return a fallback value instead, recording the panic for later inspection.
+//! These require the `std` feature.
+//!
+//! Before aborting, any [`hooks`] are run to flush logs and notify other processes.
+//! The way the program is terminated can be customized using [`terminate`].
+//! Messages are printed to stderr, or to a custom [`sink`] such as a serial port.
 //!
 //! Using `#[nounwind]` is clearer than using a drop guard,
-//! and in some versions of Rust can provide a better error message.
-//! In particular, on recent versions of rust using `#[nounwind]` will print a messages like "panic in a function that cannot unwind".
+//! and provides a better error message.
+//! When a panic attempts to escape a `#[nounwind]` function,
+//! the path and location of the function are printed before aborting,
+//! for example ``panic escaped #[nounwind] function `mycrate::ffi::foo` (src/ffi.rs:42)``.
 //!
 //! Using [`panic_nounwind!`] is preferable to `abort_unwind(|| panic!(..))`, for reasons described in the [`abort_unwind`] docs.
 //!
 //! # Feature Flags
 //! The `std` feature provides superior error messages, so should be enabled wherever possible.
+//! Without it, messages are written to stderr using raw system calls on Linux `x86_64` and `aarch64`,
+//! or given to the panic handler on other platforms, unless a [`sink`] is installed.
 //!
 //! If the `std` feature cannot be enabled, and supporting versions of rust before 1.81 is needed,
 //! enable the `old-rust-nostd` feature.
 //! This will use [`libabort`] to provide a polyfill for [`std::process::abort`].
+//! It is not needed on Linux `x86_64` and `aarch64`, where raw system calls are used instead.
+//!
+//! The `panic-handler` feature defines a `#[panic_handler]` for `#![no_std]` executables,
+//! which prints the message through the [`sink`] in the same format as [`panic_nounwind!`],
+//! then aborts using the [`terminate`] strategy.
+//! It has no effect if the `std` feature is enabled.
+//! Only enable it when building a `#![no_std]` executable:
+//! any binary linking the stdlib already has a panic handler, including test harnesses and doctests,
+//! so `cargo test` fails with this feature enabled.
+//!
+//! The `global-panic-count` feature allows using `UnwindGuard` without the stdlib.
+//! Unwinding is then detected using a single count of panics shared by every thread,
+//! which the panic handler must maintain itself.
+//! As `#![no_std]` code has no thread locals, this cannot be avoided:
+//! while one thread is panicking, an `UnwindGuard` dropped normally on any other thread aborts,
+//! so this is only suitable for single-threaded programs.
+//! See the `unwind` module for details.
+//! It has no effect if the `std` feature is enabled.
+//!
+//! The `futures-core` feature implements the `Stream` trait for [`AbortOnUnwind`].
+//!
+//! The `backtrace` feature is a debugging aid,
+//! which captures a backtrace whenever [`abort_unwind`] is called,
+//! and keeps track of the enclosing sections.
+//! See the [`abort_unwind`] docs for details.
+//!
+//! # Crash Reports
+//! With the `std` feature, a machine-readable crash report is written before aborting
+//! if the `NOUNWIND_CRASH_REPORT` environment variable is set.
+//! Its value is either a file path, or `fd:N` to write to an inherited file descriptor.
+//! Reports are appended to the file, one per line.
 //!
+//! Like any environment variable, it is inherited by child processes.
+//! Their reports are appended to the same file, and can be told apart by the `pid` field.
+//! Remove the variable from the environment of a child which should not report,
+//! for example using [`Command::env_remove`].
+//!
+//! The report is a single line of JSON, with the following fields:
+//! - `message`: The error message.
+//! - `location`: The `file`, `line`, and `column` of the error, or `null` if unknown.
+//!   For a panic, this is the `#[nounwind]` function or [`abort_unwind`] section it attempted to escape.
+//! - `panic`: The panic which caused the abort, or `null` if there was none.
+//!   It has the `message` of the panic and the `location` where it occurred,
+//!   either of which may be `null` if unknown.
+//! - `thread`: The `name` and numeric `id` of the thread, either of which may be `null`.
+//! - `pid`: The process id.
+//! - `timestamp_ms`: The time of the error, in milliseconds since the Unix epoch.
+//! - `sections`: The location of each active [`abort_unwind`] section, starting with the innermost.
+//!   Without the `backtrace` feature, this only has the section which the panic escaped, if any.
+//! - `backtrace`: The backtrace as a string, or `null` before Rust 1.65.
+//!   For a panic, this is captured where the panic occurred.
+//!   The frames of the panic machinery and this crate are omitted.
+//!
+//! The details of a panic are recorded by a panic hook,
+//! which is installed the first time this crate guards a call while the variable is set,
+//! and then calls the previously installed hook.
+//! The hook captures a backtrace for every panic, including those which are caught.
+//! A `#[nounwind] const fn` cannot install the hook,
+//! so its report only has the details of the panic if another guard was used earlier.
+//!
+//! [`Command::env_remove`]: https://doc.rust-lang.org/std/process/struct.Command.html#method.env_remove
 //! [`libabort`]: https://github.com/Techcable/libabort.rs
 //! [`std::panic::abort_unwind`]: https://doc.rust-lang.org/nightly/std/panic/fn.abort_unwind.html
 //! [`noexcept` specifier]: https://en.cppreference.com/w/cpp/language/noexcept_spec.html
@@ -32,15 +116,49 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub mod catch;
+#[cfg(feature = "std")]
+mod crash_report;
+mod escaped;
+pub mod ext;
+pub mod future;
+mod guard;
+pub mod hooks;
+// the test harness links the stdlib, which defines its own panic handler
+#[cfg(all(feature = "panic-handler", not(feature = "std"), not(test)))]
+mod panic_handler;
 #[doc(hidden)]
 pub mod panic_internals;
+#[cfg(feature = "std")]
+mod sections;
+pub mod sink;
+#[cfg(nounwind_raw_syscalls)]
+mod sys;
+pub mod terminate;
+#[cfg(any(feature = "std", feature = "global-panic-count"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "std", feature = "global-panic-count"))))]
+pub mod unwind;
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use catch::catch_to;
+pub use escaped::EscapedPanic;
+pub use ext::{OptionExt, ResultExt};
+pub use future::AbortOnUnwind;
+pub use guard::AbortGuard;
+#[cfg(any(feature = "std", feature = "global-panic-count"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "std", feature = "global-panic-count"))))]
+pub use unwind::UnwindGuard;
 
 /// Indicates that a function should abort when panicking rather than unwinding.
 ///
 /// This is equivalent to the C++ [`noexcept` specifier],
 /// or the rustc-internal `#[rustc_nounwind]` attribute.
 ///
-/// This is implemented using the [`nounwind::abort_unwind`](crate::abort_unwind) function.
+/// This behaves like the [`nounwind::abort_unwind`](crate::abort_unwind) function,
+/// but the abort message names the function the panic attempted to escape.
 ///
 /// [`noexcept` specifier]: https://en.cppreference.com/w/cpp/language/noexcept_spec.html
 ///
@@ -52,88 +170,342 @@ pub mod panic_internals;
 /// }
 /// print_nounwind("foo");
 /// ```
+///
+/// ## Impl blocks
+/// Applying the attribute to an `impl` block (either inherent or a trait impl)
+/// is equivalent to applying it to every method in the block.
+/// Individual methods can opt out using `#[nounwind(skip)]`.
+/// ```
+/// use nounwind::nounwind;
+///
+/// struct Counter(u32);
+///
+/// #[nounwind]
+/// impl Counter {
+///     fn increment(&mut self) {
+///         self.0 = self.0.checked_add(1).expect("overflow");
+///     }
+///
+///     #[nounwind(skip)]
+///     fn get(&self) -> u32 {
+///         self.0
+///     }
+/// }
+/// let mut counter = Counter(0);
+/// counter.increment();
+/// assert_eq!(counter.get(), 1);
+/// ```
+///
+/// ## Traits
+/// Applying the attribute to a trait definition wraps the body of every default method.
+/// Required methods have no body to wrap,
+/// so instead their documentation states that implementations must not unwind.
+/// Implementors can enforce this contract by applying `#[nounwind]` to their `impl` block.
+/// ```
+/// use nounwind::nounwind;
+///
+/// #[nounwind]
+/// trait Callback {
+///     fn call(&self, value: u32);
+///
+///     fn call_twice(&self, value: u32) {
+///         self.call(value);
+///         self.call(value);
+///     }
+/// }
+///
+/// struct Print;
+///
+/// #[nounwind]
+/// impl Callback for Print {
+///     fn call(&self, value: u32) {
+///         println!("{value}");
+///     }
+/// }
+/// Print.call_twice(7);
+/// ```
+///
+/// ## Async functions
+/// Calling an `async fn` only constructs a future, and the body runs when the future is polled.
+/// For this reason, applying the attribute to an `async fn` guards every poll of the returned future,
+/// so a panic anywhere in the body will abort.
+/// This is implemented using [`AbortOnUnwind`].
+///
+/// The guard is created when the future is first polled,
+/// so dropping the future is only guarded once it has been polled.
+/// If the future is dropped before that,
+/// a panic while dropping the captured arguments unwinds as usual.
+/// ```
+/// #[nounwind::nounwind]
+/// async fn fetch(id: u32) -> Option<String> {
+///     Some(format!("item {id}"))
+/// }
+/// let _future = fetch(7);
+/// ```
+///
+/// ## Const functions
+/// A `const fn` cannot call closures, so its body is guarded by a drop guard instead.
+/// Any `return` in the body is rewritten to defuse the guard first.
+/// The function can still be used in const contexts,
+/// where a panic is a compile error as usual.
+///
+/// This requires Rust 1.65 or later, as it relies on labeled blocks.
+/// Older versions reject it with a compile error.
+///
+/// ## Track caller
+/// Closures cannot be marked `#[track_caller]`,
+/// so a `#[track_caller]` function would usually lose the location of its caller.
+/// To avoid this, the body of a `#[track_caller]` function is run inline,
+/// and a drop guard detects unwinding using [`std::thread::panicking`].
+/// Panics, [`panic_nounwind!`], and [`core::panic::Location::caller`]
+/// all report the location of the caller as usual.
+///
+/// This requires the `std` feature.
+/// Without it, the body is run in a closure and the caller location is lost.
+/// ```
+/// #[nounwind::nounwind]
+/// #[track_caller]
+/// fn checked_index(values: &[u32], index: usize) -> u32 {
+///     match values.get(index) {
+///         Some(&value) => value,
+///         None => nounwind::panic_nounwind!("index {index} out of bounds"),
+///     }
+/// }
+/// assert_eq!(checked_index(&[1, 2, 3], 1), 2);
+/// ```
+///
+/// [`std::thread::panicking`]: https://doc.rust-lang.org/std/thread/fn.panicking.html
+///
+/// ## Arguments
+/// The attribute accepts several optional arguments:
+/// - `message = "..."` prints a custom message before aborting,
+///   in addition to the path and location of the function.
+/// - `on_panic = handler` calls `handler(&EscapedPanic)` before aborting.
+///   See [`EscapedPanic`] for the available information.
+/// - `if = condition` only aborts when the boolean `condition` is true,
+///   similar to the C++ `noexcept(expr)` specifier.
+///   This is useful in generic code, for example `#[nounwind(if = T::NOUNWIND)]`.
+///   The condition is evaluated every time the function is called,
+///   but is optimized away when it is a constant.
+/// - `cfg(predicate)` only applies the attribute when the `cfg` predicate holds,
+///   for example `#[nounwind(cfg(debug_assertions))]`.
+///   Combined with `wrapper`, the wrapper function always exists, but only aborts when the predicate holds.
+/// - `skip` leaves the function unchanged, opting it out of an enclosing `#[nounwind]` impl block.
+/// - `wrapper = name` leaves the function unchanged, and generates a separate function `name`
+///   with the same signature, which aborts if the original function unwinds.
+///   Methods are supported, as are associated functions whose signature mentions `Self`,
+///   such as constructors returning `Self`.
+///   Other associated functions cannot be distinguished from free functions,
+///   so the call to the original function fails to compile.
+/// - `abi = "C"` gives the wrapper function the specified ABI,
+///   and `no_mangle` marks the wrapper function `#[no_mangle]`.
+///   These both require `wrapper`.
+/// - `crate = path` specifies the path to the `nounwind` crate.
+///   By default, this is found by reading `Cargo.toml`, so renamed dependencies work automatically.
+///   This is needed if a crate re-exports the attribute,
+///   and its users do not depend on `nounwind` directly.
+///
+/// When applied to an impl block or trait, the arguments apply to every method.
+/// ```
+/// fn log_panic(panic: &nounwind::EscapedPanic<'_>) {
+///     eprintln!("panic escaped FFI boundary: {:?}", panic.message());
+/// }
+///
+/// #[nounwind::nounwind(message = "invariant of Buffer broken", on_panic = log_panic)]
+/// fn checked_len(buffer: &[u8]) -> u32 {
+///     u32::try_from(buffer.len()).unwrap()
+/// }
+/// assert_eq!(checked_len(b"foo"), 3);
+/// ```
+///
+/// Exporting a C entry point, while Rust callers can still catch panics:
+/// ```
+/// #[nounwind::nounwind(wrapper = checked_div_ffi, abi = "C", no_mangle)]
+/// pub fn checked_div(a: u32, b: u32) -> u32 {
+///     a.checked_div(b).expect("division by zero")
+/// }
+/// assert_eq!(checked_div_ffi(6, 3), 2);
+/// assert!(std::panic::catch_unwind(|| checked_div(1, 0)).is_err());
+/// ```
+///
+/// A conditional `#[nounwind]`, which depends on a generic parameter:
+/// ```
+/// trait Callback {
+///     /// Indicates that `call` promises not to unwind.
+///     const NOUNWIND: bool;
+///
+///     fn call(&self);
+/// }
+///
+/// #[nounwind::nounwind(if = C::NOUNWIND)]
+/// fn invoke<C: Callback>(callback: &C) {
+///     callback.call();
+/// }
+/// ```
 #[doc(inline)]
 #[cfg(feature = "macros")]
 #[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
 pub use nounwind_macros::nounwind;
 
-macro_rules! decl_abort_unwind {
-    (
-        $(#[$common_attr:meta])*
-        pub fn abort_unwind(...);
-    ) => {
-        #[cfg(nounwind_extern_c_will_abort)]
-        $(#[$common_attr])*
-        pub extern "C" fn abort_unwind<F: FnOnce() -> R, R>(func: F) -> R {
-            func()
-        }
+/// Creates a closure which aborts if calling it unwinds.
+///
+/// This is the equivalent of [`#[nounwind]`](nounwind) for closures,
+/// as attributes on closure expressions are unstable.
+/// The result takes the same arguments as the original closure,
+/// and implements the same `Fn`, `FnMut`, and `FnOnce` traits.
+///
+/// Writing `closure!(move |x| body)` is equivalent to `move |x| abort_unwind(|| body)`.
+///
+/// Like `#[nounwind(crate = path)]`, the path to the `nounwind` crate can be specified
+/// using `closure!(crate = path, |x| body)`.
+/// This is useful when the crate is accessed through a re-export.
+///
+/// # Examples
+/// ```
+/// let mut total = 0;
+/// let mut add = nounwind::closure!(|x: u32| {
+///     total += x;
+/// });
+/// add(1);
+/// add(2);
+/// assert_eq!(total, 3);
+/// ```
+///
+/// Passing a callback into C code:
+/// ```
+/// use std::os::raw::c_int;
+///
+/// fn register_callback(callback: impl Fn(c_int) -> c_int + 'static) {
+///     // pass to a C library
+///     assert_eq!(callback(2), 4);
+/// }
+/// let factor = 2;
+/// register_callback(nounwind::closure!(move |x| x * factor));
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use nounwind_macros::closure;
 
-        #[cfg(not(nounwind_extern_c_will_abort))]
-        $(#[$common_attr])*
-        pub fn abort_unwind<F: FnOnce() -> R, R>(func: F) -> R {
-            #[cfg(any(feature = "std", feature = "old-rust-nostd"))]
-            let guard = abort_guard::AbortGuard;
-            #[cfg(all(not(feature = "old-rust-nostd"), not(feature = "std")))]
-            let guard = {
-                compile_error!(
-                    r#"Using the `nounwind` crate with this version of rust requires either `feature = "std"` or `feature = "old-rust-nostd"`"#
-                );
-                ()
-            };
-            let res = func();
-            core::mem::forget(guard);
-            res
-        }
-    }
+/// Returns the specified value if a function panics, instead of unwinding.
+///
+/// This is the attribute form of [`catch_to`],
+/// for FFI functions that must report failure using an error code rather than aborting.
+/// The message and location of the panic are stored,
+/// and can be retrieved using [`catch::last_error`].
+///
+/// Like [`catch_to`], the first call installs a process-wide panic hook
+/// to record the location of the panic, which wraps the existing hook.
+/// See the [`catch`] module for details.
+///
+/// The value is given by `#[catch_unwind(return = value)]`,
+/// and is evaluated before the function body runs.
+/// The path to the crate can be specified using `#[catch_unwind(crate = path)]`.
+///
+/// # Examples
+/// ```
+/// use std::os::raw::c_int;
+///
+/// #[nounwind::catch_unwind(return = -1)]
+/// pub extern "C" fn checked_div(a: c_int, b: c_int) -> c_int {
+///     a.checked_div(b).expect("division failed")
+/// }
+/// assert_eq!(checked_div(7, 2), 3);
+/// assert_eq!(checked_div(7, 0), -1);
+/// let error = nounwind::catch::take_last_error().unwrap();
+/// assert_eq!(error.message(), "division failed");
+/// ```
+#[cfg(all(feature = "macros", feature = "std"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "macros", feature = "std"))))]
+pub use nounwind_macros::catch_unwind;
+
+/// Invokes a closure, aborting if the closure unwinds.
+///
+/// Unlike [`abort_unwind`], this does not record where the section was entered.
+#[cfg(all(nounwind_extern_c_will_abort, not(feature = "std")))]
+#[inline(always)]
+extern "C" fn abort_unwind_raw<F: FnOnce() -> R, R>(func: F) -> R {
+    // the compiler aborts once the panic reaches this frame,
+    // so the hooks must run as the closure unwinds
+    let guard = hooks::RunOnDrop;
+    let res = func();
+    core::mem::forget(guard);
+    res
 }
 
-#[cfg(any(feature = "std", feature = "old-rust-nostd"))]
-mod abort_guard {
-    #[allow(unused)]
-    pub struct AbortGuard;
-    impl Drop for AbortGuard {
-        #[inline]
-        fn drop(&mut self) {
-            #[cfg(feature = "std")]
-            std::process::abort();
-            #[cfg(all(feature = "old-rust-nostd", not(feature = "std")))]
-            libabort::abort();
-        }
-    }
+#[cfg(all(not(nounwind_extern_c_will_abort), not(feature = "std")))]
+#[inline(always)]
+fn abort_unwind_raw<F: FnOnce() -> R, R>(func: F) -> R {
+    let guard = AbortGuard::SILENT;
+    let res = func();
+    guard.defuse();
+    res
+}
+
+/// Invokes a closure, aborting if the closure unwinds.
+///
+/// This is equivalent to the nightly-only [`std::panic::abort_unwind`] function.
+///
+/// Prefer the [`panic_nounwind!`] macro to `abort_unwind(|| panic!(...))`,
+/// as the first gives a confusing error message.
+///
+/// If the closure unwinds, this will print a second message "panic in a function that cannot unwind".
+/// This is usually a desirable outcome, but also explains why `abort_unwind(|| panic!(user_msg))` gives a confusing message.
+/// The "panic in a function that cannot unwind" message is printed after `user_msg` is, obscuring the real panic message.
+/// To make matters worse, without the `std` feature the second message includes a backtrace by default,
+/// whereas the first does not.
+/// This makes it even harder to notice the real error message.
+/// Using [`panic_nounwind!`] avoids that.
+///
+/// When `feature = "std"` is not enabled, this relies on unwinding through an `extern "C"` function to abort.
+/// On versions of Rust before 1.81,
+/// this will fall back to using [`libabort`](https://github.com/Techcable/libabort.rs).
+///
+/// # Diagnostics
+/// With the `std` feature, the abort message includes the location where `abort_unwind` was called.
+/// The location is only recorded if the closure unwinds,
+/// so entering a section has almost no overhead.
+///
+/// Enabling the `backtrace` feature also captures a backtrace every time `abort_unwind` is called,
+/// which is printed alongside its location.
+/// Calls can then be nested, in which case the location of every enclosing call is printed,
+/// starting from the innermost one.
+/// This respects the `RUST_BACKTRACE` environment variable.
+/// Capturing a backtrace is expensive, so this feature should only be used for debugging.
+///
+/// [`std::panic::abort_unwind`]: https://doc.rust-lang.org/nightly/std/panic/fn.abort_unwind.html
+///
+/// # Examples
+/// ```
+/// fn print_nounwind(msg: &str) {
+///     nounwind::abort_unwind(|| {
+///         println!("{msg}");
+///     });
+/// }
+/// print_nounwind("foo");
+/// ```
+#[inline(always)]
+#[track_caller]
+pub fn abort_unwind<F: FnOnce() -> R, R>(func: F) -> R {
+    abort_unwind_at(core::panic::Location::caller(), func)
 }
 
-decl_abort_unwind! {
-    /// Invokes a closure, aborting if the closure unwinds.
-    ///
-    /// This is equivalent to the nightly-only [`std::panic::abort_unwind`] function.
-    ///
-    /// Prefer the [`panic_nounwind!`] macro to `abort_unwind(|| panic!(...))`,
-    /// as the first gives a confusing error message.
-    ///
-    /// As of Rust 1.92, this will print a second panic message "panic in a function that cannot unwind".
-    /// This is usually a desirable outcome, but also explains why `abort_unwind(|| panic!(user_msg))` gives a confusing message.
-    /// The "panic in a function that cannot unwind" message is printed after `user_msg` is, obscuring the real panic message.
-    /// To make matters worse, the second backtrace is enabled by default,
-    /// whereas the first is disabled by default.
-    /// This makes it even harder to notice the real error message.
-    /// Using [`panic_nounwind!`] avoids that.
-    ///
-    /// On older versions of Rust, and when `feature = "std"` is not enabled,
-    /// this will fall back to using [`libabort`](https://github.com/Techcable/libabort.rs).
-    ///
-    /// [`std::panic::abort_unwind`]: https://doc.rust-lang.org/nightly/std/panic/fn.abort_unwind.html
-    ///
-    /// # Examples
-    /// ```
-    /// fn print_nounwind(msg: &str) {
-    ///     nounwind::abort_unwind(|| {
-    ///         println!("{msg}");
-    ///     });
-    /// }
-    /// print_nounwind("foo");
-    /// ```
-    #[inline(always)]
-    pub fn abort_unwind(...);
+/// Like [`abort_unwind`], but reporting the specified location as where the section was entered.
+///
+/// Used by internal callers, which would otherwise report their own location.
+#[inline(always)]
+pub(crate) fn abort_unwind_at<F: FnOnce() -> R, R>(
+    location: &'static core::panic::Location<'static>,
+    func: F,
+) -> R {
+    #[cfg(feature = "std")]
+    {
+        sections::run(location, func)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = location;
+        abort_unwind_raw(func)
+    }
 }
 
 /// Equivalent to [`core::panic!`], but guaranteed to abort the program instead of unwinding.
@@ -179,6 +551,54 @@ macro_rules! panic_nounwind {
     };
 }
 
+/// Equivalent to [`panic_nounwind!`], but always prints a full backtrace.
+///
+/// This ignores the `RUST_BACKTRACE` environment variable,
+/// which is useful for soundness failures that must be debugged from a single occurrence.
+///
+/// The message is printed through the [`sink`] rather than the panic hook,
+/// followed by the backtrace.
+/// Backtraces require the `std` feature and Rust 1.65,
+/// otherwise this behaves like [`panic_nounwind_nobacktrace!`].
+///
+/// # Examples
+/// ```no_run
+/// # let len = 3;
+/// nounwind::panic_nounwind_force_backtrace!("buffer length {len} exceeds capacity");
+/// ```
+#[macro_export]
+macro_rules! panic_nounwind_force_backtrace {
+    ($($arg:tt)*) => {
+        $crate::panic_internals::panic_nounwind_fmt(
+            format_args!($($arg)*),
+            $crate::panic_internals::BacktraceStyle::Force,
+        )
+    };
+}
+
+/// Equivalent to [`panic_nounwind!`], but never prints a backtrace.
+///
+/// This ignores the `RUST_BACKTRACE` environment variable,
+/// which is useful for expected fatal conditions such as running out of memory,
+/// where a backtrace would only be noise.
+///
+/// The message is printed through the [`sink`] rather than the panic hook.
+///
+/// # Examples
+/// ```no_run
+/// # let size = 4096;
+/// nounwind::panic_nounwind_nobacktrace!("memory allocation of {size} bytes failed");
+/// ```
+#[macro_export]
+macro_rules! panic_nounwind_nobacktrace {
+    ($($arg:tt)*) => {
+        $crate::panic_internals::panic_nounwind_fmt(
+            format_args!($($arg)*),
+            $crate::panic_internals::BacktraceStyle::Suppress,
+        )
+    };
+}
+
 /// Equivalent to [`core::assert!`], but guaranteed to abort the program instead of unwinding.
 ///
 /// This function is useful for checking invalid state which cannot possibly be repaired.
@@ -207,6 +627,195 @@ macro_rules! assert_nounwind {
     }
 }
 
+/// Equivalent to [`core::assert_eq!`], but guaranteed to abort the program instead of unwinding.
+///
+/// The message includes the `Debug` output of both values, just like [`core::assert_eq!`].
+/// See the [`assert_nounwind!`] macro for details.
+///
+/// # Examples
+/// ```
+/// let len = 3;
+/// nounwind::assert_eq_nounwind!(len, 3);
+/// nounwind::assert_eq_nounwind!(len, 3, "unexpected length for {}", "foo");
+/// ```
+///
+/// A failed assertion prints the same message as the stdlib:
+/// ```no_run
+/// nounwind::assert_eq_nounwind!(1 + 1, 3);
+/// // assertion `left == right` failed
+/// //   left: 2
+/// //  right: 3
+/// ```
+#[macro_export]
+macro_rules! assert_eq_nounwind {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    $crate::panic_internals::assert_failed(
+                        $crate::panic_internals::AssertKind::Eq,
+                        &*left_val,
+                        &*right_val,
+                        ::core::option::Option::None,
+                    );
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    $crate::panic_internals::assert_failed(
+                        $crate::panic_internals::AssertKind::Eq,
+                        &*left_val,
+                        &*right_val,
+                        ::core::option::Option::Some(format_args!($($arg)+)),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Equivalent to [`core::assert_ne!`], but guaranteed to abort the program instead of unwinding.
+///
+/// The message includes the `Debug` output of both values, just like [`core::assert_ne!`].
+/// See the [`assert_nounwind!`] macro for details.
+///
+/// # Examples
+/// ```
+/// let len = 3;
+/// nounwind::assert_ne_nounwind!(len, 0);
+/// nounwind::assert_ne_nounwind!(len, 0, "empty {}", "foo");
+/// ```
+#[macro_export]
+macro_rules! assert_ne_nounwind {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if *left_val == *right_val {
+                    $crate::panic_internals::assert_failed(
+                        $crate::panic_internals::AssertKind::Ne,
+                        &*left_val,
+                        &*right_val,
+                        ::core::option::Option::None,
+                    );
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if *left_val == *right_val {
+                    $crate::panic_internals::assert_failed(
+                        $crate::panic_internals::AssertKind::Ne,
+                        &*left_val,
+                        &*right_val,
+                        ::core::option::Option::Some(format_args!($($arg)+)),
+                    );
+                }
+            }
+        }
+    };
+}
+
+/// Asserts that an expression matches a pattern,
+/// guaranteed to abort the program instead of unwinding.
+///
+/// This is equivalent to the unstable `core::assert_matches!` macro,
+/// including the `Debug` output of the value in the message.
+/// See the [`assert_nounwind!`] macro for details.
+///
+/// # Examples
+/// ```
+/// let value = Some(7);
+/// nounwind::assert_matches_nounwind!(value, Some(_));
+/// nounwind::assert_matches_nounwind!(value, Some(x) if x > 3);
+/// nounwind::assert_matches_nounwind!(value, Some(1..=9), "out of range: {:?}", value);
+/// ```
+#[macro_export]
+macro_rules! assert_matches_nounwind {
+    ($left:expr, $(|)? $($pattern:pat_param)|+ $(if $guard:expr)? $(,)?) => {
+        match $left {
+            $($pattern)|+ $(if $guard)? => {}
+            ref left_val => {
+                $crate::panic_internals::assert_matches_failed(
+                    left_val,
+                    stringify!($($pattern)|+ $(if $guard)?),
+                    ::core::option::Option::None,
+                );
+            }
+        }
+    };
+    ($left:expr, $(|)? $($pattern:pat_param)|+ $(if $guard:expr)?, $($arg:tt)+) => {
+        match $left {
+            $($pattern)|+ $(if $guard)? => {}
+            ref left_val => {
+                $crate::panic_internals::assert_matches_failed(
+                    left_val,
+                    stringify!($($pattern)|+ $(if $guard)?),
+                    ::core::option::Option::Some(format_args!($($arg)+)),
+                );
+            }
+        }
+    };
+}
+
+/// Equivalent to [`core::debug_assert!`], but guaranteed to abort the program instead of unwinding.
+///
+/// Like [`assert_nounwind!`], but only enabled with `debug_assertions`.
+/// Soundness checks should usually use [`assert_nounwind!`] instead.
+///
+/// # Examples
+/// ```
+/// nounwind::debug_assert_nounwind!(3 + 7 > 2);
+/// nounwind::debug_assert_nounwind!(3 + 7 > 2, "message {}", 7);
+/// ```
+#[macro_export]
+macro_rules! debug_assert_nounwind {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_nounwind!($($arg)*);
+        }
+    };
+}
+
+/// Equivalent to [`core::debug_assert_eq!`], but guaranteed to abort the program instead of unwinding.
+///
+/// Like [`assert_eq_nounwind!`], but only enabled with `debug_assertions`.
+///
+/// # Examples
+/// ```
+/// nounwind::debug_assert_eq_nounwind!(3 + 7, 10);
+/// ```
+#[macro_export]
+macro_rules! debug_assert_eq_nounwind {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_eq_nounwind!($($arg)*);
+        }
+    };
+}
+
+/// Equivalent to [`core::debug_assert_ne!`], but guaranteed to abort the program instead of unwinding.
+///
+/// Like [`assert_ne_nounwind!`], but only enabled with `debug_assertions`.
+///
+/// # Examples
+/// ```
+/// nounwind::debug_assert_ne_nounwind!(3 + 7, 0);
+/// ```
+#[macro_export]
+macro_rules! debug_assert_ne_nounwind {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_ne_nounwind!($($arg)*);
+        }
+    };
+}
+
 /// Equivalent to [`core::unreachable!`], but guaranteed to abort the program instead of unwinding.
 ///
 /// This function is useful if it would be undefined behavior to continue.
@@ -270,5 +879,8 @@ macro_rules! unreachable_nounwind {
 #[inline(never)]
 #[track_caller]
 pub fn panic_nounwind(s: &'static str) -> ! {
-    panic_internals::panic_nounwind_fmt(format_args!("{}", s))
+    panic_internals::panic_nounwind_fmt(
+        format_args!("{}", s),
+        panic_internals::BacktraceStyle::Default,
+    )
 }
@@ -0,0 +1,32 @@
+//! A `#[panic_handler]` for `#![no_std]` executables,
+//! enabled by the `panic-handler` feature.
+//!
+//! Prints the message and location through the installed [`crate::sink`],
+//! or directly to stderr on Linux,
+//! using the same format as [`crate::panic_nounwind!`],
+//! then runs the [hooks](crate::hooks) and terminates using the current [strategy](crate::terminate).
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the panic handler is entered.
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+#[cold]
+fn panic(info: &PanicInfo<'_>) -> ! {
+    if ENTERED.swap(true, Ordering::AcqRel) {
+        // Either printing the message panicked,
+        // or the default abort mechanism panicked to trigger an abort.
+        // Panicking again would recurse forever.
+        crate::terminate::halt()
+    }
+    // only enabled on Rust 1.81 and later
+    #[cfg(nounwind_panic_info_message)]
+    #[allow(clippy::incompatible_msrv)]
+    crate::sink::print_fatal(format_args!("{}", info.message()), info.location());
+    // Before Rust 1.81, the message can only be accessed through the `Display` impl
+    #[cfg(not(nounwind_panic_info_message))]
+    crate::sink::print_fatal(format_args!("{}", info), None);
+    crate::guard::abort()
+}
@@ -1,4 +1,7 @@
-//! Internals for the [`crate::panic_nounwind!`] macro and friends.
+//! Internals for the [`crate::panic_nounwind!`] macro, the `#[nounwind]` attribute, and friends.
+
+use core::any::Any;
+use core::panic::Location;
 
 #[cold]
 #[inline(never)]
@@ -7,6 +10,78 @@ pub fn unreachable_nounwind() -> ! {
     crate::panic_nounwind("internal error: entered unreachable code")
 }
 
+/// The comparison made by a failed assertion,
+/// used by [`assert_failed`].
+#[derive(Copy, Clone, Debug)]
+pub enum AssertKind {
+    /// `assert_eq_nounwind!`
+    Eq,
+    /// `assert_ne_nounwind!`
+    Ne,
+}
+
+/// Implementation detail of the [`crate::assert_eq_nounwind!`] and [`crate::assert_ne_nounwind!`] macros.
+///
+/// Prints the same message as the stdlib, including the `Debug` output of both values.
+#[cold]
+#[inline(never)]
+#[track_caller]
+pub fn assert_failed<T, U>(
+    kind: AssertKind,
+    left: &T,
+    right: &U,
+    args: Option<core::fmt::Arguments<'_>>,
+) -> !
+where
+    T: core::fmt::Debug + ?Sized,
+    U: core::fmt::Debug + ?Sized,
+{
+    let op = match kind {
+        AssertKind::Eq => "==",
+        AssertKind::Ne => "!=",
+    };
+    assert_failed_inner(op, &left, &right, args)
+}
+
+/// Implementation detail of the [`crate::assert_matches_nounwind!`] macro.
+#[cold]
+#[inline(never)]
+#[track_caller]
+pub fn assert_matches_failed<T: core::fmt::Debug + ?Sized>(
+    left: &T,
+    right: &str,
+    args: Option<core::fmt::Arguments<'_>>,
+) -> ! {
+    /// Prints the pattern without quotes.
+    struct Pattern<'a>(&'a str);
+    impl core::fmt::Debug for Pattern<'_> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+    assert_failed_inner("matches", &left, &Pattern(right), args)
+}
+
+/// Non-generic version of [`assert_failed`], to reduce code size.
+#[track_caller]
+fn assert_failed_inner(
+    op: &str,
+    left: &dyn core::fmt::Debug,
+    right: &dyn core::fmt::Debug,
+    args: Option<core::fmt::Arguments<'_>>,
+) -> ! {
+    match args {
+        Some(args) => do_panic_nounwind(format_args!(
+            "assertion `left {} right` failed: {}\n  left: {:?}\n right: {:?}",
+            op, args, left, right
+        )),
+        None => do_panic_nounwind(format_args!(
+            "assertion `left {} right` failed\n  left: {:?}\n right: {:?}",
+            op, left, right
+        )),
+    }
+}
+
 /// Implementation detail of the [`crate::panic_nounwind!`] macro,
 /// used to optimize for constant strings.
 ///
@@ -52,10 +127,24 @@ pub fn do_panic_nounwind(args: core::fmt::Arguments<'_>) -> ! {
     if let Some(msg) = args.as_str() {
         crate::panic_nounwind(msg)
     } else {
-        panic_nounwind_fmt(args)
+        panic_nounwind_fmt(args, BacktraceStyle::Default)
     }
 }
 
+/// Controls whether a backtrace is printed by [`panic_nounwind_fmt`].
+///
+/// This is an implementation detail of the [`crate::panic_nounwind!`] family of macros,
+/// and is exempt from semver guarantees.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BacktraceStyle {
+    /// Defer to the panic hook, which respects `RUST_BACKTRACE`.
+    Default,
+    /// Always print a full backtrace, bypassing the panic hook.
+    Force,
+    /// Never print a backtrace, bypassing the panic hook.
+    Suppress,
+}
+
 /// Calls `panic!` with the specified message, but guaranteed to abort instead of unwinding.
 ///
 /// This is an implementation detail of the [`crate::panic_nounwind!`] macro,
@@ -63,27 +152,285 @@ pub fn do_panic_nounwind(args: core::fmt::Arguments<'_>) -> ! {
 /// As such, it is exempt from semver guarantees.
 ///
 /// This mirrors the [`core::panicking::panic_nounwind_fmt`] function in the standard library,
-/// but without the parameter controlling backtrace suppression.
+/// but the parameter controlling backtrace suppression can also force a backtrace.
 ///
 /// [`core::panicking::panic_nounwind_fmt`]: https://github.com/rust-lang/rust/blob/1.92.0/library/core/src/panicking.rs#L83-L95
 #[track_caller]
 #[inline(never)]
 #[cold]
-pub fn panic_nounwind_fmt(f: core::fmt::Arguments<'_>) -> ! {
-    // This gives a better error message than using abort_unwind.
-    // That prints two panic messages: First the real panic message,
-    // and second a "panic in a function which can't unwind".
-    // Even worse, the second message always includes a backtrace
-    // unrelated to the real backtrace.
-    //
-    // TODO: Take advantage of libabort or something like it to provide these better messages on #[no_std]
+pub fn panic_nounwind_fmt(f: core::fmt::Arguments<'_>, backtrace: BacktraceStyle) -> ! {
     #[cfg(feature = "std")]
     {
-        let _guard = crate::abort_guard::AbortGuard;
-        panic!("{}", f)
+        let location = Location::caller();
+        crate::crash_report::write(f, Some(location), crate::crash_report::Cause::None);
+        if backtrace == BacktraceStyle::Default && crate::sink::sink().is_none() {
+            // This gives a better error message than using abort_unwind.
+            // That prints two panic messages: First the real panic message,
+            // and second a "panic in a function which can't unwind".
+            // Even worse, the second message always includes a backtrace
+            // unrelated to the real backtrace.
+            let _guard = crate::AbortGuard::SILENT;
+            panic!("{}", f)
+        }
+        // The sink replaces the panic hook, so it works even if stderr is closed
+        crate::sink::print_fatal(f, Some(location));
+        // only enabled on Rust 1.65 and later
+        #[cfg(nounwind_std_backtrace)]
+        #[allow(clippy::incompatible_msrv)]
+        if backtrace == BacktraceStyle::Force {
+            let trace = std::backtrace::Backtrace::force_capture();
+            crate::sink::print_fatal(format_args!("stack backtrace:\n{:#}", trace), None);
+        }
+        crate::guard::abort()
     }
     #[cfg(not(feature = "std"))]
     {
-        crate::abort_unwind(|| panic!("{}", f))
+        // backtraces are unavailable without the stdlib
+        let _ = backtrace;
+        // Print through the sink, or directly to stderr on Linux
+        if crate::sink::print_fatal(f, Some(Location::caller())) {
+            crate::guard::abort()
+        }
+        // Otherwise, the panic handler is the only way to print a message
+        crate::abort_unwind_at(Location::caller(), || panic!("{}", f))
+    }
+}
+
+/// The configuration of a `#[nounwind]` function,
+/// given by the arguments to the attribute.
+///
+/// This is generated by the `#[nounwind]` macro and is exempt from semver guarantees.
+#[derive(Copy, Clone)]
+pub struct NounwindFn {
+    /// Whether to abort if the function unwinds, given by `#[nounwind(if = condition)]`.
+    ///
+    /// If this is false, the function is allowed to unwind as usual.
+    pub enabled: bool,
+    /// The message to print before aborting, given by `#[nounwind(message = "...")]`.
+    pub message: Option<&'static str>,
+    /// The handler to call before aborting, given by `#[nounwind(on_panic = handler)]`.
+    pub on_panic: Option<fn(&crate::EscapedPanic<'_>)>,
+    /// Returns the type name of a function nested inside the `#[nounwind]` function,
+    /// from which the path of the function is determined.
+    ///
+    /// This is a function pointer because [`core::any::type_name`] is not a `const fn`.
+    pub name: fn() -> &'static str,
+    /// Returns the location of the `#[nounwind]` function.
+    ///
+    /// This is a function pointer because [`Location::caller`] is not a `const fn`.
+    pub location: fn() -> &'static Location<'static>,
+}
+
+/// Implementation detail of [`NounwindFn::name`].
+#[inline(always)]
+pub fn type_name_of<T>(_value: T) -> &'static str {
+    core::any::type_name::<T>()
+}
+
+/// Determine the path of a `#[nounwind]` function from the type name of a nested function.
+///
+/// Strips the name of the nested function, along with any closures or async blocks.
+fn function_path(mut name: &'static str) -> &'static str {
+    if let Some(index) = name.rfind("::") {
+        name = &name[..index];
+    }
+    while let Some(stripped) = name.strip_suffix("::{{closure}}") {
+        name = stripped;
+    }
+    name
+}
+
+/// Invoke the body of a `#[nounwind]` function,
+/// aborting if the body unwinds.
+///
+/// If the function is not enabled, it is called directly.
+///
+/// With the `std` feature, the panic is caught so its payload can be given to the `on_panic` handler.
+/// Otherwise, a drop guard detects the unwinding.
+#[inline(always)]
+pub fn call_nounwind<F: FnOnce() -> R, R>(info: &NounwindFn, func: F) -> R {
+    if !info.enabled {
+        return func();
+    }
+    #[cfg(feature = "std")]
+    {
+        crate::crash_report::prepare();
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(func)) {
+            Ok(res) => res,
+            Err(payload) => escaped_panic(info, Some(&*payload)),
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        crate::abort_unwind_at((info.location)(), move || {
+            let guard = EscapeGuard { info };
+            let res = func();
+            core::mem::forget(guard);
+            res
+        })
+    }
+}
+
+#[cfg(not(feature = "std"))]
+struct EscapeGuard<'a> {
+    info: &'a NounwindFn,
+}
+#[cfg(not(feature = "std"))]
+impl Drop for EscapeGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        escaped_panic(self.info, None)
+    }
+}
+
+/// Implementation detail of `#[nounwind] const fn`.
+///
+/// A `const fn` cannot call closures, so [`call_nounwind`] cannot be used.
+/// Instead, the body is guarded by this value, which aborts if dropped.
+/// The guard must be explicitly defused once the body completes.
+///
+/// Dropping the guard in a `const fn` is a compile error,
+/// so any control flow which would skip defusing the guard is rejected at compile time.
+///
+/// Unlike the other guards, this cannot call `crash_report::prepare` from a `const fn`,
+/// so crash reports rely on another guard having installed the panic hook.
+pub struct ConstGuard {
+    info: NounwindFn,
+}
+impl ConstGuard {
+    #[inline(always)]
+    pub const fn new(info: NounwindFn) -> Self {
+        ConstGuard { info }
+    }
+
+    #[inline(always)]
+    pub const fn defuse(self) {
+        core::mem::forget(self);
+    }
+}
+impl Drop for ConstGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.info.enabled {
+            escaped_panic(&self.info, None)
+        }
+    }
+}
+
+/// Implementation detail of `#[track_caller] #[nounwind] fn`.
+///
+/// Closures cannot be marked `#[track_caller]`,
+/// so running the body inside [`call_nounwind`] would lose the location of the caller.
+/// With the `std` feature, the body is run inline and guarded by a [`TrackCallerGuard`].
+/// Otherwise, unwinding cannot be detected without a closure,
+/// so this falls back to [`call_nounwind`].
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __track_caller_nounwind {
+    ($info:expr, $body:block) => {{
+        let guard = $crate::panic_internals::TrackCallerGuard::new($info);
+        let result = $body;
+        guard.defuse();
+        result
+    }};
+}
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __track_caller_nounwind {
+    ($info:expr, $body:block) => {
+        $crate::panic_internals::call_nounwind(
+            &$info,
+            #[inline(always)]
+            move || $body,
+        )
+    };
+}
+
+/// Aborts if dropped while unwinding,
+/// used by `#[track_caller] #[nounwind] fn`.
+///
+/// Unlike [`ConstGuard`], early returns do not need to defuse the guard,
+/// as [`std::thread::panicking`] distinguishes unwinding from regular control flow.
+#[cfg(feature = "std")]
+pub struct TrackCallerGuard {
+    info: NounwindFn,
+    /// Set unless the guard was created while already panicking,
+    /// for example in a destructor that runs during unwinding.
+    armed: bool,
+}
+#[cfg(feature = "std")]
+impl TrackCallerGuard {
+    #[inline(always)]
+    pub fn new(info: NounwindFn) -> Self {
+        crate::crash_report::prepare();
+        TrackCallerGuard {
+            armed: info.enabled && !std::thread::panicking(),
+            info,
+        }
+    }
+
+    #[inline(always)]
+    pub fn defuse(self) {
+        core::mem::forget(self);
+    }
+}
+#[cfg(feature = "std")]
+impl Drop for TrackCallerGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.armed && std::thread::panicking() {
+            escaped_panic(&self.info, None)
+        }
+    }
+}
+
+/// The message printed when a panic escapes a `#[nounwind]` function.
+struct EscapedMessage<'a> {
+    info: &'a NounwindFn,
+    location: &'static Location<'static>,
+}
+impl core::fmt::Display for EscapedMessage<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(message) = self.info.message {
+            writeln!(f, "{}", message)?;
+        }
+        write!(
+            f,
+            "panic escaped #[nounwind] function `{}` ({}:{})",
+            function_path((self.info.name)()),
+            self.location.file(),
+            self.location.line(),
+        )
+    }
+}
+
+/// Handle a panic that attempted to unwind out of a `#[nounwind]` function.
+///
+/// Calls the `on_panic` handler, prints the message, and aborts.
+#[cold]
+#[inline(never)]
+fn escaped_panic(info: &NounwindFn, payload: Option<&(dyn Any + Send)>) -> ! {
+    let location = (info.location)();
+    if let Some(handler) = info.on_panic {
+        let details = crate::EscapedPanic { payload, location };
+        crate::abort_unwind_at(location, || handler(&details));
+    }
+    let message = EscapedMessage { info, location };
+    #[cfg(feature = "std")]
+    crate::crash_report::write(
+        format_args!("{}", message),
+        Some(location),
+        match payload {
+            Some(payload) => crate::crash_report::Cause::Caught(payload),
+            None => crate::crash_report::Cause::Unwinding,
+        },
+    );
+    if crate::sink::print_fatal(format_args!("{}", message), None) {
+        crate::guard::abort()
     }
+    // Without the stdlib, a sink, or raw system calls,
+    // the panic handler is the only way to print a message.
+    crate::panic_nounwind!("{}", message)
 }
@@ -0,0 +1,147 @@
+//! Tracks the sections entered by [`crate::abort_unwind`] on each thread,
+//! so they can be reported if one of them aborts.
+//!
+//! By default, entering a section costs nothing:
+//! its location is kept in a guard on the stack, which is forgotten if the closure returns.
+//! Only if the closure unwinds does the guard record the location and abort.
+//! The enclosing sections are unknown at that point, so only the innermost one is reported.
+//!
+//! With the `backtrace` feature, the sections also form an intrusive linked list,
+//! where each node lives on the stack of the corresponding `abort_unwind` call.
+//! This avoids any allocation when entering a section,
+//! but still accesses a thread local on every call.
+// The `backtrace` feature documents that it requires Rust 1.65
+#![cfg_attr(feature = "backtrace", allow(clippy::incompatible_msrv))]
+
+use std::cell::Cell;
+use std::fmt;
+use std::panic::Location;
+#[cfg(feature = "backtrace")]
+use std::ptr;
+
+#[cfg(not(feature = "backtrace"))]
+thread_local! {
+    /// The section which a panic escaped, recorded just before aborting.
+    static ESCAPED: Cell<Option<&'static Location<'static>>> = Cell::new(None);
+}
+
+#[cfg(feature = "backtrace")]
+thread_local! {
+    /// The innermost section of the current thread, or null if there is none.
+    static CURRENT: Cell<*const Section> = Cell::new(ptr::null());
+}
+
+#[cfg(feature = "backtrace")]
+struct Section {
+    location: &'static Location<'static>,
+    /// The enclosing section, or null if this is the outermost one.
+    parent: *const Section,
+    backtrace: std::backtrace::Backtrace,
+}
+
+/// Invoke the specified function, aborting and reporting the active sections if it unwinds.
+#[inline(always)]
+pub(crate) fn run<F: FnOnce() -> R, R>(location: &'static Location<'static>, func: F) -> R {
+    crate::crash_report::prepare();
+    #[cfg(feature = "backtrace")]
+    let section = Section {
+        location,
+        parent: current(),
+        backtrace: std::backtrace::Backtrace::capture(),
+    };
+    #[cfg(feature = "backtrace")]
+    set_current(&section);
+    let guard = SectionGuard { location };
+    let res = func();
+    #[cfg(feature = "backtrace")]
+    set_current(section.parent);
+    std::mem::forget(guard);
+    res
+}
+
+#[cfg(feature = "backtrace")]
+#[inline]
+fn current() -> *const Section {
+    // the thread local may already be destroyed if this is called from another destructor
+    CURRENT.try_with(Cell::get).unwrap_or(ptr::null())
+}
+
+#[cfg(feature = "backtrace")]
+#[inline]
+fn set_current(section: *const Section) {
+    let _ = CURRENT.try_with(|current| current.set(section));
+}
+
+/// Invoke the function with every active section on this thread,
+/// starting with the innermost.
+#[cfg(feature = "backtrace")]
+fn for_each_section(mut func: impl FnMut(&Section)) {
+    let mut section = current();
+    // SAFETY: Every section in the list is still alive,
+    // as their `abort_unwind` calls have not returned yet.
+    while let Some(current) = unsafe { section.as_ref() } {
+        func(current);
+        section = current.parent;
+    }
+}
+
+/// Invoke the function with the location of every known section on this thread,
+/// starting with the innermost.
+///
+/// Without the `backtrace` feature, this is only the section a panic escaped, if any.
+pub(crate) fn for_each_location(mut func: impl FnMut(&'static Location<'static>)) {
+    #[cfg(feature = "backtrace")]
+    for_each_section(|section| func(section.location));
+    #[cfg(not(feature = "backtrace"))]
+    if let Some(location) = ESCAPED.try_with(Cell::get).ok().flatten() {
+        func(location);
+    }
+}
+
+/// Aborts if dropped, reporting the section which was escaped.
+struct SectionGuard {
+    location: &'static Location<'static>,
+}
+impl Drop for SectionGuard {
+    #[cold]
+    fn drop(&mut self) {
+        #[cfg(not(feature = "backtrace"))]
+        let _ = ESCAPED.try_with(|escaped| escaped.set(Some(self.location)));
+        crate::crash_report::write(
+            format_args!("panic in a function that cannot unwind"),
+            Some(self.location),
+            crate::crash_report::Cause::Unwinding,
+        );
+        crate::sink::print_fatal(format_args!("{}", Report), None);
+        crate::guard::abort();
+    }
+}
+
+/// Reports every known section, starting with the innermost.
+struct Report;
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("panic in a function that cannot unwind")?;
+        let mut res = Ok(());
+        #[cfg(not(feature = "backtrace"))]
+        for_each_location(|location| {
+            res =
+                res.and_then(|()| write!(f, "\n  in abort_unwind section entered at {}", location));
+        });
+        #[cfg(feature = "backtrace")]
+        for_each_section(|section| {
+            res = res.and_then(|()| {
+                write!(
+                    f,
+                    "\n  in abort_unwind section entered at {}",
+                    section.location
+                )?;
+                if section.backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+                    write!(f, "\nsection backtrace:\n{}", section.backtrace)?;
+                }
+                Ok(())
+            });
+        });
+        res
+    }
+}
@@ -0,0 +1,174 @@
+//! A pluggable destination for fatal error messages.
+//!
+//! By default, messages are written to stderr with the `std` feature.
+//! Without the stdlib, they are written to stderr using raw system calls on Linux `x86_64` and `aarch64`,
+//! or given to the panic handler on other platforms.
+//! Installing a [`Sink`] overrides this,
+//! which is useful for `#![no_std]` code that has its own output device,
+//! or when stderr is redirected or closed.
+//!
+//! The sink receives every message printed by this crate before aborting,
+//! including the message of [`crate::panic_nounwind!`],
+//! which then bypasses the panic handler and panic hook entirely.
+//! Messages printed by the compiler or the panic handler itself are not affected,
+//! unless the panic handler is the one provided by the `panic-handler` feature.
+//! Like [`log::set_logger`], the sink can only be set once.
+//!
+//! [`log::set_logger`]: https://docs.rs/log/0.4/log/fn.set_logger.html
+//!
+//! # Examples
+//! ```
+//! use nounwind::sink::Sink;
+//!
+//! struct Uart;
+//! impl Sink for Uart {
+//!     fn write_str(&self, s: &str) {
+//!         // write the bytes to the device
+//!         # let _ = s;
+//!     }
+//! }
+//! static UART: Uart = Uart;
+//! nounwind::sink::set_sink(&UART).unwrap();
+//! ```
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write as _};
+use core::panic::Location;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A destination for fatal error messages.
+pub trait Sink: Sync {
+    /// Write a string to the output.
+    ///
+    /// Errors should be ignored, as the program is about to abort anyways.
+    fn write_str(&self, s: &str);
+
+    /// Write a fatal error message, which may have an associated location.
+    ///
+    /// The default implementation formats the message using [`Sink::write_str`],
+    /// similar to the way the stdlib formats a panic message.
+    fn write_fatal(&self, message: fmt::Arguments<'_>, location: Option<&Location<'_>>) {
+        let mut writer = Writer(self);
+        let _ = match location {
+            Some(location) => write!(writer, "fatal error at {}:\n{}\n", location, message),
+            None => writeln!(writer, "{}", message),
+        };
+    }
+}
+
+/// Adapts a [`Sink`] to implement [`fmt::Write`].
+struct Writer<'a, S: Sink + ?Sized>(&'a S);
+impl<S: Sink + ?Sized> fmt::Write for Writer<'_, S> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s);
+        Ok(())
+    }
+}
+
+const UNINITIALIZED: usize = 0;
+const INITIALIZING: usize = 1;
+const INITIALIZED: usize = 2;
+
+static STATE: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+static SINK: SinkCell = SinkCell(UnsafeCell::new(None));
+
+struct SinkCell(UnsafeCell<Option<&'static dyn Sink>>);
+// SAFETY: The cell is only written once, before `STATE` is set to `INITIALIZED`
+unsafe impl Sync for SinkCell {}
+
+/// Install the sink for fatal error messages.
+///
+/// Returns an error if a sink has already been installed.
+pub fn set_sink(sink: &'static dyn Sink) -> Result<(), SetSinkError> {
+    match STATE.compare_exchange(
+        UNINITIALIZED,
+        INITIALIZING,
+        Ordering::Acquire,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => {
+            // SAFETY: The state guarantees no other thread is accessing the cell
+            unsafe {
+                *SINK.0.get() = Some(sink);
+            }
+            STATE.store(INITIALIZED, Ordering::Release);
+            Ok(())
+        }
+        Err(_) => Err(SetSinkError(())),
+    }
+}
+
+/// The installed sink, or `None` if [`set_sink`] has not been called.
+#[inline]
+pub fn sink() -> Option<&'static dyn Sink> {
+    if STATE.load(Ordering::Acquire) == INITIALIZED {
+        // SAFETY: The cell is never written after it is initialized
+        unsafe { *SINK.0.get() }
+    } else {
+        None
+    }
+}
+
+/// The error returned by [`set_sink`] if a sink has already been installed.
+#[derive(Debug)]
+pub struct SetSinkError(());
+impl fmt::Display for SetSinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a fatal message sink has already been installed")
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for SetSinkError {}
+
+/// Print a fatal error message,
+/// using the installed sink or falling back to stderr.
+///
+/// Returns false if there is nowhere to print the message,
+/// in which case it should be given to the panic handler.
+#[cold]
+pub(crate) fn print_fatal(message: fmt::Arguments<'_>, location: Option<&Location<'_>>) -> bool {
+    match sink().or(STDERR) {
+        Some(sink) => {
+            sink.write_fatal(message, location);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The sink used if none is installed,
+/// or `None` if there is no way to access stderr.
+#[cfg(any(feature = "std", nounwind_raw_syscalls))]
+const STDERR: Option<&'static dyn Sink> = Some(&Stderr);
+#[cfg(not(any(feature = "std", nounwind_raw_syscalls)))]
+const STDERR: Option<&'static dyn Sink> = None;
+
+/// Writes to stderr, using the stdlib or a raw `write` system call.
+#[cfg(any(feature = "std", nounwind_raw_syscalls))]
+struct Stderr;
+#[cfg(feature = "std")]
+impl Sink for Stderr {
+    fn write_str(&self, s: &str) {
+        use std::io::Write;
+        // ignore errors, as we are about to abort anyways
+        let _ = std::io::stderr().write_all(s.as_bytes());
+    }
+
+    fn write_fatal(&self, message: fmt::Arguments<'_>, location: Option<&Location<'_>>) {
+        use std::io::Write;
+        // hold the lock, so the message is not interleaved with other output
+        let stderr = std::io::stderr();
+        let mut out = stderr.lock();
+        let _ = match location {
+            Some(location) => write!(out, "fatal error at {}:\n{}\n", location, message),
+            None => writeln!(out, "{}", message),
+        };
+    }
+}
+#[cfg(all(not(feature = "std"), nounwind_raw_syscalls))]
+impl Sink for Stderr {
+    fn write_str(&self, s: &str) {
+        crate::sys::write_stderr(s.as_bytes());
+    }
+}
@@ -0,0 +1,149 @@
+//! Raw Linux system calls, which work without the stdlib or libc.
+//!
+//! Only supported on `x86_64` and `aarch64`.
+
+#[cfg(target_arch = "x86_64")]
+#[allow(dead_code)] // some are only used without the stdlib
+mod nr {
+    pub const WRITE: usize = 1;
+    pub const RT_SIGACTION: usize = 13;
+    pub const RT_SIGPROCMASK: usize = 14;
+    pub const GETPID: usize = 39;
+    pub const GETTID: usize = 186;
+    pub const TGKILL: usize = 234;
+    pub const EXIT_GROUP: usize = 231;
+}
+#[cfg(target_arch = "aarch64")]
+#[allow(dead_code)] // some are only used without the stdlib
+mod nr {
+    pub const WRITE: usize = 64;
+    pub const RT_SIGACTION: usize = 134;
+    pub const RT_SIGPROCMASK: usize = 135;
+    pub const GETPID: usize = 172;
+    pub const GETTID: usize = 178;
+    pub const TGKILL: usize = 131;
+    pub const EXIT_GROUP: usize = 94;
+}
+
+/// Invoke a system call with up to four arguments.
+///
+/// # Safety
+/// The system call must be safe to invoke with the specified arguments.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn syscall4(nr: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> isize {
+    let ret: isize;
+    core::arch::asm!(
+        "syscall",
+        inlateout("rax") nr as isize => ret,
+        in("rdi") a1,
+        in("rsi") a2,
+        in("rdx") a3,
+        in("r10") a4,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+/// Invoke a system call with up to four arguments.
+///
+/// # Safety
+/// The system call must be safe to invoke with the specified arguments.
+#[cfg(target_arch = "aarch64")]
+#[inline]
+unsafe fn syscall4(nr: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> isize {
+    let ret: isize;
+    core::arch::asm!(
+        "svc 0",
+        in("x8") nr,
+        inlateout("x0") a1 as isize => ret,
+        in("x1") a2,
+        in("x2") a3,
+        in("x3") a4,
+        options(nostack),
+    );
+    ret
+}
+
+/// Invoke a system call with up to three arguments.
+///
+/// # Safety
+/// The system call must be safe to invoke with the specified arguments.
+#[inline]
+unsafe fn syscall3(nr: usize, a1: usize, a2: usize, a3: usize) -> isize {
+    syscall4(nr, a1, a2, a3, 0)
+}
+
+/// Send the specified signal to the current thread.
+///
+/// Returns if the signal is handled or ignored.
+pub fn raise(signal: i32) {
+    // SAFETY: These system calls do not access memory
+    unsafe {
+        let pid = syscall3(nr::GETPID, 0, 0, 0);
+        let tid = syscall3(nr::GETTID, 0, 0, 0);
+        syscall3(nr::TGKILL, pid as usize, tid as usize, signal as usize);
+    }
+}
+
+/// Immediately exit the process with the specified code,
+/// without running any destructors or `atexit` handlers.
+pub fn exit(code: i32) -> ! {
+    // SAFETY: Exiting the process is always safe, and exit_group never returns
+    unsafe {
+        syscall3(nr::EXIT_GROUP, code as usize, 0, 0);
+        core::hint::unreachable_unchecked()
+    }
+}
+
+/// Write the bytes to stderr, ignoring any errors.
+#[cfg(not(feature = "std"))]
+pub fn write_stderr(mut bytes: &[u8]) {
+    const EINTR: isize = -4;
+    while !bytes.is_empty() {
+        // SAFETY: The kernel only reads the specified bytes
+        let ret = unsafe { syscall3(nr::WRITE, 2, bytes.as_ptr() as usize, bytes.len()) };
+        if ret > 0 {
+            bytes = &bytes[ret as usize..];
+        } else if ret != EINTR {
+            // closed, or otherwise unusable
+            return;
+        }
+    }
+}
+
+/// Abort the process by raising `SIGABRT`,
+/// after restoring the default handler and unblocking the signal.
+///
+/// Returns if the signal could not be raised.
+#[cfg(not(feature = "std"))]
+pub fn abort() {
+    const SIGABRT: i32 = 6;
+    const SIG_UNBLOCK: usize = 1;
+    // the size of the kernel's signal mask, which differs from libc's `sigset_t`
+    const SIGSET_SIZE: usize = 8;
+    // the kernel's `struct sigaction`, zeroed to request `SIG_DFL`
+    let action = [0usize; 4];
+    let mask: u64 = 1 << (SIGABRT - 1);
+    // SAFETY: The kernel only reads from the pointers,
+    // which are valid for the specified sizes
+    unsafe {
+        syscall4(
+            nr::RT_SIGACTION,
+            SIGABRT as usize,
+            action.as_ptr() as usize,
+            0,
+            SIGSET_SIZE,
+        );
+        syscall4(
+            nr::RT_SIGPROCMASK,
+            SIG_UNBLOCK,
+            &mask as *const u64 as usize,
+            0,
+            SIGSET_SIZE,
+        );
+    }
+    raise(SIGABRT);
+}
@@ -0,0 +1,245 @@
+//! Controls how the program is terminated once this crate decides to abort.
+//!
+//! This is similar to [`std::set_terminate`] in C++.
+//! The strategy applies to every abort performed by this crate,
+//! and runs after the [hooks](crate::hooks).
+//! It does not apply when the compiler itself aborts,
+//! for example when a panic unwinds through an `extern "C"` function.
+//!
+//! The default strategy can be selected at compile time,
+//! by setting the `NOUNWIND_ABORT_STRATEGY` environment variable to one of
+//! `abort`, `trap`, `sigabrt`, `signal=N`, or `exit=N`.
+//! It can be changed at runtime using [`set_strategy`] or [`set_terminate`].
+//!
+//! [`std::set_terminate`]: https://en.cppreference.com/w/cpp/error/set_terminate
+//!
+//! # Examples
+//! ```
+//! use nounwind::terminate::{self, Strategy};
+//!
+//! fn reset_device() -> ! {
+//!     // a firmware reset would go here
+//!     std::process::exit(3)
+//! }
+//! terminate::set_terminate(reset_device);
+//! # terminate::set_strategy(Strategy::Abort);
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The way the program is terminated.
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Use the default abort mechanism.
+    ///
+    /// With the `std` feature, this is [`std::process::abort`].
+    /// Otherwise, this raises `SIGABRT` using raw system calls on Linux `x86_64` and `aarch64`.
+    /// If that fails, or on other platforms, this uses [`libabort`] if the `old-rust-nostd` feature is enabled,
+    /// or panics inside an `extern "C"` function on Rust 1.81 and later.
+    /// On earlier versions of Rust without either, a trap instruction is used instead.
+    ///
+    /// [`std::process::abort`]: https://doc.rust-lang.org/std/process/fn.abort.html
+    /// [`libabort`]: https://github.com/Techcable/libabort.rs
+    Abort,
+    /// Execute a trap instruction, such as `ud2` on x86.
+    ///
+    /// Falls back to [`Strategy::Abort`] on unsupported platforms, or before Rust 1.59.
+    Trap,
+    /// Send the specified signal to the current thread, using a raw system call.
+    ///
+    /// If the signal is handled or ignored, falls back to [`Strategy::Abort`].
+    /// Only supported on Linux `x86_64` and `aarch64`, falling back to [`Strategy::Abort`] elsewhere.
+    Signal(i32),
+    /// Immediately exit the process with the specified code,
+    /// without running any destructors or `atexit` handlers.
+    ///
+    /// Uses a raw system call on Linux `x86_64` and `aarch64`,
+    /// or [`std::process::exit`] on other platforms with the `std` feature.
+    /// Otherwise, falls back to [`Strategy::Abort`].
+    ///
+    /// [`std::process::exit`]: https://doc.rust-lang.org/std/process/fn.exit.html
+    Exit(i32),
+    /// Call a user-defined handler, installed by [`set_terminate`].
+    ///
+    /// If the handler panics, the program is aborted using [`Strategy::Abort`].
+    Custom(fn() -> !),
+}
+
+/// The default strategy, selected at compile time.
+const DEFAULT: Strategy = include!(concat!(env!("OUT_DIR"), "/default_strategy.rs"));
+
+/// The kind of the current strategy, or [`KIND_DEFAULT`] if it has not been set.
+static KIND: AtomicUsize = AtomicUsize::new(KIND_DEFAULT);
+/// The payload of the current strategy.
+static DATA: AtomicUsize = AtomicUsize::new(0);
+/// Set once the program starts terminating.
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+const KIND_DEFAULT: usize = 0;
+const KIND_ABORT: usize = 1;
+const KIND_TRAP: usize = 2;
+const KIND_SIGNAL: usize = 3;
+const KIND_EXIT: usize = 4;
+const KIND_CUSTOM: usize = 5;
+
+/// Change the strategy used to terminate the program.
+///
+/// This should be called during initialization,
+/// as a thread which aborts concurrently may observe a mix of the old and new strategy.
+pub fn set_strategy(strategy: Strategy) {
+    let (kind, data) = match strategy {
+        Strategy::Abort => (KIND_ABORT, 0),
+        Strategy::Trap => (KIND_TRAP, 0),
+        Strategy::Signal(signal) => (KIND_SIGNAL, signal as usize),
+        Strategy::Exit(code) => (KIND_EXIT, code as usize),
+        Strategy::Custom(handler) => (KIND_CUSTOM, handler as usize),
+    };
+    DATA.store(data, Ordering::Release);
+    KIND.store(kind, Ordering::Release);
+}
+
+/// Install a handler to terminate the program, returning the previous strategy.
+///
+/// This is equivalent to `set_strategy(Strategy::Custom(handler))`.
+pub fn set_terminate(handler: fn() -> !) -> Strategy {
+    let previous = strategy();
+    set_strategy(Strategy::Custom(handler));
+    previous
+}
+
+/// The strategy currently used to terminate the program.
+pub fn strategy() -> Strategy {
+    let kind = KIND.load(Ordering::Acquire);
+    let data = DATA.load(Ordering::Acquire);
+    match kind {
+        KIND_ABORT => Strategy::Abort,
+        KIND_TRAP => Strategy::Trap,
+        KIND_SIGNAL => Strategy::Signal(data as i32),
+        KIND_EXIT => Strategy::Exit(data as i32),
+        // SAFETY: The data was created from a `fn() -> !` in `set_strategy`
+        KIND_CUSTOM => Strategy::Custom(unsafe { core::mem::transmute::<usize, fn() -> !>(data) }),
+        _ => DEFAULT,
+    }
+}
+
+/// Terminate the program using the current strategy.
+#[cold]
+#[inline(never)]
+pub(crate) fn terminate() -> ! {
+    if TERMINATING.swap(true, Ordering::AcqRel) {
+        // the strategy itself failed
+        default_abort()
+    }
+    match strategy() {
+        Strategy::Abort => default_abort(),
+        Strategy::Trap => trap(),
+        Strategy::Signal(signal) => {
+            #[cfg(nounwind_raw_syscalls)]
+            crate::sys::raise(signal);
+            let _ = signal;
+            default_abort()
+        }
+        Strategy::Exit(code) => {
+            #[cfg(nounwind_raw_syscalls)]
+            crate::sys::exit(code);
+            #[cfg(all(not(nounwind_raw_syscalls), feature = "std"))]
+            std::process::exit(code);
+            #[allow(unreachable_code)]
+            {
+                let _ = code;
+                default_abort()
+            }
+        }
+        Strategy::Custom(handler) => {
+            // a panicking handler falls back to the default abort
+            let _guard = crate::AbortGuard::SILENT;
+            handler()
+        }
+    }
+}
+
+/// Execute a trap instruction, or fall back to [`default_abort`].
+fn trap() -> ! {
+    try_trap();
+    default_abort()
+}
+
+/// Stop the program without panicking,
+/// using a trap instruction or spinning forever if none is available.
+///
+/// Used when the default abort mechanism is unavailable,
+/// because it would panic from inside the panic handler.
+#[cfg(all(feature = "panic-handler", not(feature = "std")))]
+pub(crate) fn halt() -> ! {
+    try_trap();
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Execute a trap instruction, if supported on this platform.
+#[inline(always)]
+fn try_trap() {
+    // SAFETY: These instructions raise an exception, and never return
+    #[cfg(all(nounwind_asm, any(target_arch = "x86", target_arch = "x86_64")))]
+    unsafe {
+        core::arch::asm!("ud2", options(noreturn, nomem, nostack))
+    }
+    #[cfg(all(nounwind_asm, target_arch = "aarch64"))]
+    unsafe {
+        core::arch::asm!("brk #1", options(noreturn, nomem, nostack))
+    }
+    #[cfg(all(nounwind_asm, any(target_arch = "riscv32", target_arch = "riscv64")))]
+    unsafe {
+        core::arch::asm!("unimp", options(noreturn, nomem, nostack))
+    }
+}
+
+/// Abort the program using [`Strategy::Abort`].
+fn default_abort() -> ! {
+    // Raising SIGABRT matches the behavior of the stdlib
+    #[cfg(all(not(feature = "std"), nounwind_raw_syscalls))]
+    crate::sys::abort();
+    #[cfg(feature = "std")]
+    {
+        std::process::abort()
+    }
+    #[cfg(all(not(feature = "std"), feature = "old-rust-nostd"))]
+    {
+        libabort::abort()
+    }
+    #[cfg(all(
+        not(feature = "std"),
+        not(feature = "old-rust-nostd"),
+        nounwind_extern_c_will_abort
+    ))]
+    {
+        extern "C" fn panic_cannot_unwind() -> ! {
+            panic!("aborting")
+        }
+        panic_cannot_unwind()
+    }
+    #[cfg(all(
+        not(feature = "std"),
+        not(feature = "old-rust-nostd"),
+        not(nounwind_extern_c_will_abort),
+        nounwind_raw_syscalls
+    ))]
+    {
+        // SIGABRT could not be raised, so fall back to a trap
+        try_trap();
+        crate::sys::exit(134)
+    }
+    #[cfg(all(
+        not(feature = "std"),
+        not(feature = "old-rust-nostd"),
+        not(nounwind_extern_c_will_abort),
+        not(nounwind_raw_syscalls)
+    ))]
+    {
+        compile_error!(
+            r#"Using the `nounwind` crate with this version of rust requires either `feature = "std"` or `feature = "old-rust-nostd"`"#
+        );
+    }
+}
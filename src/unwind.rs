@@ -0,0 +1,155 @@
+//! Detecting whether the current thread is unwinding.
+//!
+//! With the `std` feature, this uses [`std::thread::panicking`].
+//! Without the stdlib, there is no way to detect unwinding,
+//! so the `global-panic-count` feature keeps its own count of panics in progress.
+//! This count must be maintained by the panic handler,
+//! by calling [`begin_panic`] before unwinding starts
+//! and [`end_panic`] once the panic has been caught.
+//! Because `#![no_std]` code has no thread locals, the count is shared by every thread.
+//! This is a hard limitation of the feature, which makes it unsuitable for multi-threaded programs:
+//! while one thread is panicking, guards dropped normally on other threads abort as well.
+//!
+//! [`std::thread::panicking`]: https://doc.rust-lang.org/std/thread/fn.panicking.html
+
+use core::panic::Location;
+
+use crate::AbortGuard;
+
+/// A guard which aborts if it is dropped during unwinding,
+/// but does nothing when dropped normally.
+///
+/// Unlike [`AbortGuard`], this does not need to be defused,
+/// so early returns and the `?` operator are allowed.
+/// This is useful for protecting partially-updated data structures from unwinding,
+/// while still allowing regular control flow.
+///
+/// If the guard is created while the thread is already panicking,
+/// for example in a destructor that runs during unwinding,
+/// then it is disarmed and never aborts.
+///
+/// See the [module documentation](self) for how unwinding is detected.
+///
+/// # Without the stdlib
+/// Without the `std` feature, this requires the `global-panic-count` feature,
+/// and has two important limitations:
+/// - The guard does nothing unless the panic handler calls [`begin_panic`] and [`end_panic`].
+///   Otherwise, it never detects unwinding and never aborts.
+/// - The count of panics is shared by every thread.
+///   While any thread is panicking, every guard on every other thread aborts,
+///   even if it is dropped normally.
+///
+/// # Examples
+/// ```
+/// use nounwind::UnwindGuard;
+///
+/// fn relink(nodes: &mut [Option<usize>], from: usize, to: usize) -> Option<()> {
+///     let _guard = UnwindGuard::new("linked list is inconsistent");
+///     let next = nodes.get(from)?.to_owned();
+///     *nodes.get_mut(to)? = next;
+///     nodes[from] = Some(to);
+///     Some(())
+/// }
+/// let mut nodes = vec![Some(1), None];
+/// assert_eq!(relink(&mut nodes, 0, 1), Some(()));
+/// assert_eq!(relink(&mut nodes, 0, 7), None);
+/// ```
+#[must_use = "the guard only protects the scope it is alive in"]
+#[derive(Debug)]
+pub struct UnwindGuard {
+    /// The guard which aborts, or `None` if created while already panicking.
+    bomb: Option<AbortGuard>,
+}
+impl UnwindGuard {
+    /// Create a guard which prints the specified message before aborting.
+    ///
+    /// The location of the caller is printed as well.
+    #[inline]
+    #[track_caller]
+    pub fn new(message: &'static str) -> Self {
+        UnwindGuard::from_bomb(AbortGuard::new(message))
+    }
+
+    /// Create a guard which prints the specified location before aborting.
+    #[inline]
+    pub fn at(location: &'static Location<'static>) -> Self {
+        UnwindGuard::from_bomb(AbortGuard::at(location))
+    }
+
+    #[inline]
+    fn from_bomb(bomb: AbortGuard) -> Self {
+        if panicking() {
+            bomb.defuse();
+            UnwindGuard { bomb: None }
+        } else {
+            UnwindGuard { bomb: Some(bomb) }
+        }
+    }
+}
+impl Drop for UnwindGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(bomb) = self.bomb.take() {
+            if panicking() {
+                drop(bomb);
+            } else {
+                bomb.defuse();
+            }
+        }
+    }
+}
+
+/// Check if the current thread is panicking.
+///
+/// With the `std` feature, this is equivalent to [`std::thread::panicking`].
+/// Otherwise, this checks the count maintained by [`begin_panic`] and [`end_panic`].
+///
+/// [`std::thread::panicking`]: https://doc.rust-lang.org/std/thread/fn.panicking.html
+#[inline]
+pub fn panicking() -> bool {
+    #[cfg(feature = "std")]
+    {
+        std::thread::panicking()
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        nostd::PANIC_COUNT.load(core::sync::atomic::Ordering::Relaxed) > 0
+    }
+}
+
+/// Record that a panic has started unwinding.
+///
+/// Without the `std` feature, the panic handler should call this before it begins unwinding.
+/// With the `std` feature, this does nothing.
+#[inline]
+pub fn begin_panic() {
+    #[cfg(not(feature = "std"))]
+    nostd::PANIC_COUNT.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+}
+
+/// Record that a panic has been caught, and has finished unwinding.
+///
+/// Without the `std` feature, this should be called once for every call to [`begin_panic`],
+/// after the corresponding panic has been caught.
+/// Calling it more often is a bug in the panic handler,
+/// which is checked by a debug assertion.
+/// With the `std` feature, this does nothing.
+#[inline]
+pub fn end_panic() {
+    #[cfg(not(feature = "std"))]
+    {
+        let previous = nostd::PANIC_COUNT.fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
+        debug_assert!(
+            previous > 0,
+            "end_panic called without a matching begin_panic"
+        );
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod nostd {
+    use core::sync::atomic::AtomicUsize;
+
+    /// The number of panics in progress, across all threads.
+    pub static PANIC_COUNT: AtomicUsize = AtomicUsize::new(0);
+}
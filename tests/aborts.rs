@@ -0,0 +1,996 @@
+//! Test code that aborts the process.
+//!
+//! Each test re-runs this test binary as a subprocess,
+//! which runs only that test and aborts,
+//! then checks the exit status and the output of the subprocess.
+#![cfg(unix)]
+
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Output};
+
+/// The environment variable naming the test a subprocess should run.
+const CHILD_VAR: &str = "NOUNWIND_TEST_CHILD";
+
+/// The signal raised by `abort()`.
+const SIGABRT: i32 = 6;
+
+/// Run the closure in a subprocess, returning the output once it exits.
+///
+/// The `name` must be the name of the calling test.
+/// Inside the subprocess, the closure is run and the process exits successfully,
+/// so a test can detect that the closure returned instead of aborting.
+fn run_child(name: &str, func: impl FnOnce()) -> Output {
+    run_child_with(name, &[], func)
+}
+
+/// Like [`run_child`], but also setting environment variables in the subprocess.
+fn run_child_with(name: &str, env: &[(&str, &str)], func: impl FnOnce()) -> Output {
+    if std::env::var_os(CHILD_VAR).map_or(false, |child| child == name) {
+        func();
+        std::process::exit(0);
+    }
+    Command::new(std::env::current_exe().unwrap())
+        .args([name, "--exact", "--nocapture", "--test-threads=1"])
+        .env(CHILD_VAR, name)
+        .env("RUST_BACKTRACE", "0")
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// The line number printed by the subprocess as `line N`.
+///
+/// This lets a test check the location reported for the following line.
+fn printed_line(output: &Output) -> u32 {
+    // the test harness prints the name of the test on the same line
+    let stdout = stdout(output);
+    let (_, line) = stdout.rsplit_once("line ").expect("missing line number");
+    line.trim_end().parse().unwrap()
+}
+
+/// Assert the subprocess was killed by `SIGABRT`.
+#[track_caller]
+fn assert_aborted(output: &Output) {
+    assert_eq!(
+        output.status.signal(),
+        Some(SIGABRT),
+        "expected abort, got {}\nstderr:\n{}",
+        output.status,
+        stderr(output)
+    );
+}
+
+#[test]
+fn abort_unwind_aborts() {
+    let output = run_child("abort_unwind_aborts", || {
+        nounwind::abort_unwind(|| panic!("original panic"));
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("original panic"), "{}", stderr);
+    // before Rust 1.81, the fallback without the stdlib aborts silently
+    if cfg!(any(feature = "std", nounwind_extern_c_will_abort)) {
+        assert!(
+            stderr.contains("panic in a function that cannot unwind"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+/// Enclosing sections are only reported with the `backtrace` feature.
+#[test]
+#[cfg(feature = "std")]
+fn nested_sections() {
+    let output = run_child("nested_sections", || {
+        nounwind::abort_unwind(|| {
+            println!("line {}", line!() + 1);
+            nounwind::abort_unwind(|| panic!("inner panic"));
+        });
+    });
+    assert_aborted(&output);
+    let inner = printed_line(&output);
+    let stderr = stderr(&output);
+    let entered = |line: u32| {
+        format!(
+            "in abort_unwind section entered at tests/aborts.rs:{}:",
+            line
+        )
+    };
+    assert!(stderr.contains(&entered(inner)), "{}", stderr);
+    assert_eq!(
+        stderr.contains(&entered(inner - 2)),
+        cfg!(feature = "backtrace"),
+        "{}",
+        stderr
+    );
+}
+
+/// The location of the wrapper is reported, rather than a location inside the library.
+#[test]
+#[cfg(feature = "std")]
+fn abort_on_unwind_location() {
+    use nounwind::future::FutureExt as _;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct PanicOnDrop;
+    impl Future for PanicOnDrop {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            Poll::Ready(())
+        }
+    }
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("dropped future")
+        }
+    }
+    let output = run_child("abort_on_unwind_location", || {
+        drop(PanicOnDrop.abort_on_unwind());
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("in abort_unwind section entered at tests/aborts.rs:"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn panic_nounwind_aborts() {
+    let output = run_child("panic_nounwind_aborts", || {
+        let value = 42;
+        let _ = std::panic::catch_unwind(|| nounwind::panic_nounwind!("fatal {}", value));
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("fatal 42"), "{}", stderr);
+}
+
+#[test]
+fn abort_guard_dropped() {
+    let output = run_child("abort_guard_dropped", || {
+        println!("line {}", line!() + 1);
+        let guard = nounwind::AbortGuard::new("guard message");
+        drop(guard);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    let expected = format!(
+        "guard message (guard created at tests/aborts.rs:{}:",
+        printed_line(&output)
+    );
+    assert!(stderr.contains(&expected), "{}", stderr);
+}
+
+/// Without the stdlib, this relies on `begin_panic` to detect unwinding.
+#[test]
+#[cfg(any(feature = "std", feature = "global-panic-count"))]
+fn unwind_guard_unwinding() {
+    let output = run_child("unwind_guard_unwinding", || {
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = nounwind::UnwindGuard::new("guard message");
+            nounwind::unwind::begin_panic();
+            panic!("original panic")
+        });
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("original panic"), "{}", stderr);
+    assert!(
+        stderr.contains("guard message (guard created at tests/aborts.rs:"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn hooks_run_in_order() {
+    fn first() {
+        eprintln!("first hook");
+    }
+    fn second() {
+        eprintln!("second hook");
+    }
+    let output = run_child("hooks_run_in_order", || {
+        nounwind::hooks::register(first).unwrap();
+        nounwind::hooks::register(second).unwrap();
+        nounwind::panic_nounwind!("fatal error");
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    let position = |s: &str| {
+        stderr
+            .find(s)
+            .unwrap_or_else(|| panic!("missing {:?}:\n{}", s, stderr))
+    };
+    // the hooks run after the message is printed, right before aborting
+    assert!(
+        position("fatal error") < position("first hook"),
+        "{}",
+        stderr
+    );
+    assert!(
+        position("first hook") < position("second hook"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn panicking_hook_aborts() {
+    fn hook() {
+        panic!("hook panicked")
+    }
+    fn next_hook() {
+        eprintln!("next hook");
+    }
+    let output = run_child("panicking_hook_aborts", || {
+        nounwind::hooks::register(hook).unwrap();
+        nounwind::hooks::register(next_hook).unwrap();
+        let value = 42;
+        // this aborts without unwinding, so the hooks don't run during a panic
+        nounwind::panic_nounwind_nobacktrace!("fatal {}", value);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("fatal 42"), "{}", stderr);
+    assert!(stderr.contains("hook panicked"), "{}", stderr);
+    // without the stdlib, the panic cannot be caught and aborts immediately
+    assert_eq!(
+        stderr.contains("next hook"),
+        cfg!(feature = "std"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn too_many_hooks() {
+    fn hook() {
+        unreachable!("hooks only run when aborting")
+    }
+    let output = run_child("too_many_hooks", || {
+        for _ in 0..nounwind::hooks::MAX_HOOKS {
+            nounwind::hooks::register(hook).unwrap();
+        }
+        assert_eq!(
+            nounwind::hooks::register(hook),
+            Err(nounwind::hooks::TooManyHooks)
+        );
+    });
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn sink_receives_message() {
+    struct Stdout;
+    impl nounwind::sink::Sink for Stdout {
+        fn write_str(&self, s: &str) {
+            use std::io::Write;
+            let _ = std::io::stdout().write_all(s.as_bytes());
+        }
+    }
+    let output = run_child("sink_receives_message", || {
+        use nounwind::sink;
+        assert!(sink::sink().is_none());
+        sink::set_sink(&Stdout).unwrap();
+        assert!(sink::sink().is_some());
+        // the sink can only be set once
+        assert!(sink::set_sink(&Stdout).is_err());
+        let value = 42;
+        nounwind::panic_nounwind!("fatal {}", value);
+    });
+    assert_aborted(&output);
+    let (stdout, stderr) = (stdout(&output), stderr(&output));
+    assert!(
+        stdout.contains("fatal error at tests/aborts.rs:"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(":\nfatal 42\n"), "{}", stdout);
+    assert!(!stderr.contains("fatal 42"), "{}", stderr);
+}
+
+#[test]
+#[cfg(all(feature = "std", nounwind_std_backtrace))]
+fn force_backtrace() {
+    let output = run_child("force_backtrace", || {
+        nounwind::panic_nounwind_force_backtrace!("fatal {}", 42);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("fatal 42\nstack backtrace:\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("aborts::force_backtrace"), "{}", stderr);
+}
+
+#[test]
+fn nobacktrace() {
+    let output = run_child_with("nobacktrace", &[("RUST_BACKTRACE", "full")], || {
+        nounwind::panic_nounwind_nobacktrace!("fatal {}", 42);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("fatal 42"), "{}", stderr);
+    assert!(!stderr.contains("backtrace"), "{}", stderr);
+}
+
+/// The assertion messages use the format of the stdlib since Rust 1.73.
+#[test]
+fn assert_eq_message() {
+    let output = run_child("assert_eq_message", || {
+        let value = 3;
+        nounwind::assert_eq_nounwind!(value, 4, "value was {}", value);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("assertion `left == right` failed: value was 3\n  left: 3\n right: 4"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn assert_ne_message() {
+    let output = run_child("assert_ne_message", || {
+        nounwind::assert_ne_nounwind!("a", "a");
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("assertion `left != right` failed\n  left: \"a\"\n right: \"a\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn assert_matches_message() {
+    let output = run_child("assert_matches_message", || {
+        nounwind::assert_matches_nounwind!(Some(5), Some(x) if x > 5);
+    });
+    assert_aborted(&output);
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains(
+            "assertion `left matches right` failed\n  left: Some(5)\n right: Some(x) if x > 5"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+mod strategy {
+    use super::*;
+    use nounwind::terminate::{self, Strategy};
+
+    /// Terminate the subprocess using the strategy, after checking it was set.
+    fn abort_with(strategy: Strategy) {
+        terminate::set_strategy(strategy);
+        match (strategy, terminate::strategy()) {
+            (Strategy::Trap, Strategy::Trap) => {}
+            (Strategy::Signal(a), Strategy::Signal(b)) | (Strategy::Exit(a), Strategy::Exit(b)) => {
+                assert_eq!(a, b)
+            }
+            (expected, actual) => panic!("expected {:?}, got {:?}", expected, actual),
+        }
+        nounwind::panic_nounwind!("fatal error");
+    }
+
+    #[test]
+    fn exit() {
+        let output = run_child("strategy::exit", || abort_with(Strategy::Exit(3)));
+        assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+        assert!(stderr(&output).contains("fatal error"));
+    }
+
+    /// Signals are sent using raw system calls, falling back to `SIGABRT`.
+    #[test]
+    fn signal() {
+        const SIGTERM: i32 = 15;
+        let output = run_child("strategy::signal", || abort_with(Strategy::Signal(SIGTERM)));
+        let expected = if cfg!(nounwind_raw_syscalls) {
+            SIGTERM
+        } else {
+            SIGABRT
+        };
+        assert_eq!(
+            output.status.signal(),
+            Some(expected),
+            "{}",
+            stderr(&output)
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn trap() {
+        const SIGILL: i32 = 4;
+        const SIGTRAP: i32 = 5;
+        let output = run_child("strategy::trap", || abort_with(Strategy::Trap));
+        let expected = if !cfg!(nounwind_asm) {
+            SIGABRT
+        } else if cfg!(target_arch = "aarch64") {
+            SIGTRAP
+        } else {
+            SIGILL
+        };
+        assert_eq!(
+            output.status.signal(),
+            Some(expected),
+            "{}",
+            stderr(&output)
+        );
+    }
+
+    #[test]
+    fn custom() {
+        fn handler() -> ! {
+            eprintln!("custom handler");
+            std::process::exit(7)
+        }
+        let output = run_child("strategy::custom", || {
+            let previous = terminate::set_terminate(handler);
+            assert!(matches!(previous, Strategy::Abort));
+            assert!(matches!(terminate::strategy(), Strategy::Custom(_)));
+            nounwind::panic_nounwind!("fatal error");
+        });
+        assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+        assert!(stderr(&output).contains("custom handler"));
+    }
+
+    /// A panicking handler falls back to the default abort.
+    #[test]
+    fn custom_panics() {
+        fn handler() -> ! {
+            panic!("handler panicked")
+        }
+        let output = run_child("strategy::custom_panics", || {
+            terminate::set_terminate(handler);
+            nounwind::panic_nounwind_nobacktrace!("fatal error");
+        });
+        assert_aborted(&output);
+        assert!(stderr(&output).contains("handler panicked"));
+    }
+}
+
+#[cfg(feature = "macros")]
+mod escaped {
+    use super::*;
+
+    #[nounwind::nounwind]
+    fn escapes() {
+        panic!("escaped panic")
+    }
+
+    #[nounwind::nounwind(message = "custom message")]
+    fn escapes_with_message() {
+        panic!("escaped panic")
+    }
+
+    struct PanicOnDrop;
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("dropped argument")
+        }
+    }
+
+    /// A future which is never ready.
+    struct Pending;
+    impl std::future::Future for Pending {
+        type Output = ();
+
+        fn poll(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<()> {
+            std::task::Poll::Pending
+        }
+    }
+
+    #[nounwind::nounwind]
+    async fn never_finishes(_arg: PanicOnDrop) {
+        Pending.await
+    }
+
+    #[test]
+    fn async_drop_after_poll() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Wake, Waker};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+        let output = run_child("escaped::async_drop_after_poll", || {
+            let waker = Waker::from(Arc::new(NoopWaker));
+            let mut future = Box::pin(never_finishes(PanicOnDrop));
+            assert!(future
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+            let _ = std::panic::catch_unwind(move || drop(future));
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        assert!(stderr.contains("dropped argument"), "{}", stderr);
+    }
+
+    /// The guard is only created by the first poll.
+    #[test]
+    fn async_drop_before_poll() {
+        let output = run_child("escaped::async_drop_before_poll", || {
+            let future = never_finishes(PanicOnDrop);
+            assert!(std::panic::catch_unwind(move || drop(future)).is_err());
+        });
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+
+    struct Ratio(u32);
+    impl Ratio {
+        #[nounwind::nounwind(wrapper = new_nounwind)]
+        fn new(numerator: u32, denominator: u32) -> Self {
+            assert!(denominator != 0, "zero denominator");
+            Ratio(numerator / denominator)
+        }
+    }
+
+    #[test]
+    fn associated_wrapper() {
+        let output = run_child("escaped::associated_wrapper", || {
+            assert_eq!(Ratio::new_nounwind(6, 3).0, 2);
+            let _ = std::panic::catch_unwind(|| Ratio::new_nounwind(1, 0));
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        assert!(stderr.contains("zero denominator"), "{}", stderr);
+        assert!(
+            stderr.contains(
+                "panic escaped #[nounwind] function `aborts::escaped::Ratio::new_nounwind`"
+            ),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn nounwind_names_function() {
+        let output = run_child("escaped::nounwind_names_function", || {
+            let _ = std::panic::catch_unwind(escapes);
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        assert!(stderr.contains("escaped panic"), "{}", stderr);
+        assert!(
+            stderr.contains(
+                "panic escaped #[nounwind] function `aborts::escaped::escapes` (tests/aborts.rs:"
+            ),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn nounwind_custom_message() {
+        let output = run_child("escaped::nounwind_custom_message", || {
+            let _ = std::panic::catch_unwind(escapes_with_message);
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        assert!(
+            stderr.contains("custom message\npanic escaped #[nounwind] function `aborts::escaped::escapes_with_message`"),
+            "{}",
+            stderr
+        );
+    }
+
+    // `#[nounwind] const fn` requires labeled blocks
+    #[cfg(nounwind_label_break_value)]
+    #[nounwind::nounwind]
+    const fn const_div(a: u32, b: u32) -> u32 {
+        if b == 0 {
+            panic!("const division by zero");
+        }
+        a / b
+    }
+
+    #[test]
+    #[cfg(nounwind_label_break_value)]
+    fn const_fn_at_runtime() {
+        const QUOTIENT: u32 = const_div(6, 3);
+        let output = run_child("escaped::const_fn_at_runtime", || {
+            assert_eq!(QUOTIENT, 2);
+            let divisor = "0".parse().unwrap();
+            let _ = std::panic::catch_unwind(|| const_div(1, divisor));
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        assert!(stderr.contains("const division by zero"), "{}", stderr);
+        assert!(
+            stderr.contains("panic escaped #[nounwind] function `aborts::escaped::const_div`"),
+            "{}",
+            stderr
+        );
+    }
+
+    // without the stdlib, the body runs in a closure which loses the caller
+    #[cfg(feature = "std")]
+    #[track_caller]
+    #[nounwind::nounwind]
+    fn checked_index(values: &[u32], index: usize) -> u32 {
+        match values.get(index) {
+            Some(&value) => value,
+            None => panic!("index {} out of range", index),
+        }
+    }
+
+    /// The panic reports the caller of a `#[track_caller]` function.
+    #[test]
+    #[cfg(feature = "std")]
+    fn track_caller_location() {
+        let output = run_child("escaped::track_caller_location", || {
+            println!("line {}", line!() + 1);
+            let _ = std::panic::catch_unwind(|| checked_index(&[1, 2], 5));
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        let caller = format!("tests/aborts.rs:{}:", printed_line(&output));
+        assert!(stderr.contains(&caller), "{}", stderr);
+        assert!(stderr.contains("index 5 out of range"), "{}", stderr);
+        assert!(
+            stderr.contains("panic escaped #[nounwind] function `aborts::escaped::checked_index`"),
+            "{}",
+            stderr
+        );
+    }
+
+    fn print_escaped(panic: &nounwind::EscapedPanic<'_>) {
+        let location = panic.location();
+        eprintln!(
+            "handler got {:?} for {}:{}",
+            panic.message(),
+            location.file(),
+            location.line()
+        );
+    }
+
+    #[nounwind::nounwind(on_panic = print_escaped)]
+    fn escapes_with_handler() {
+        panic!("escaped panic")
+    }
+
+    /// The handler runs before the abort message is printed.
+    #[test]
+    fn on_panic_handler() {
+        let output = run_child("escaped::on_panic_handler", || {
+            let _ = std::panic::catch_unwind(escapes_with_handler);
+        });
+        assert_aborted(&output);
+        let stderr = stderr(&output);
+        // the payload can only be caught with the stdlib
+        let message = if cfg!(feature = "std") {
+            Some("escaped panic")
+        } else {
+            None
+        };
+        let (_, handled) = stderr
+            .split_once(&format!("handler got {:?} for ", message))
+            .unwrap_or_else(|| panic!("handler not called:\n{}", stderr));
+        let (location, rest) = handled.split_once('\n').unwrap();
+        assert!(location.starts_with("tests/aborts.rs:"), "{}", stderr);
+        // the location is of the function, which the abort message also names
+        let expected = format!(
+            "panic escaped #[nounwind] function `aborts::escaped::escapes_with_handler` ({})",
+            location
+        );
+        assert!(rest.contains(&expected), "{}", stderr);
+    }
+}
+
+#[cfg(feature = "std")]
+mod crash_report {
+    use super::*;
+
+    /// Run the closure in a subprocess with crash reports enabled,
+    /// returning the parsed report once it aborts.
+    fn run_with_report(name: &str, func: impl FnOnce()) -> Json {
+        let path = std::env::temp_dir().join(format!(
+            "nounwind-{}-{}.json",
+            name.replace("::", "-"),
+            std::process::id()
+        ));
+        let output = run_child_with(
+            name,
+            &[("NOUNWIND_CRASH_REPORT", path.to_str().unwrap())],
+            func,
+        );
+        assert_aborted(&output);
+        let report = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(report.ends_with('\n'), "{}", report);
+        Json::parse(&report)
+    }
+
+    /// Check the fields which are the same for every report.
+    #[track_caller]
+    fn check_common(report: &Json) {
+        let thread = report.get("thread");
+        assert!(matches!(thread.get("name"), Json::String(_) | Json::Null));
+        assert!(thread.get("id").as_u64() > 0);
+        assert!(report.get("pid").as_u64() > 0);
+        assert!(report.get("timestamp_ms").as_u64() > 0);
+    }
+
+    /// Check the backtrace starts in this file,
+    /// skipping the frames of the panic machinery and the library.
+    #[track_caller]
+    fn check_backtrace(report: &Json) {
+        if cfg!(nounwind_std_backtrace) {
+            let backtrace = report.get("backtrace").as_str();
+            let first = backtrace.trim_start().split_once(": ").unwrap().1;
+            assert!(first.starts_with("aborts::"), "{}", backtrace);
+        } else {
+            assert_eq!(*report.get("backtrace"), Json::Null);
+        }
+    }
+
+    /// Check the location is in this file, returning the line.
+    #[track_caller]
+    fn check_location(location: &Json) -> u64 {
+        assert_eq!(location.get("file").as_str(), "tests/aborts.rs");
+        assert!(location.get("column").as_u64() > 0);
+        location.get("line").as_u64()
+    }
+
+    #[cfg(feature = "macros")]
+    #[nounwind::nounwind]
+    fn escapes() {
+        panic!("{}", line!())
+    }
+
+    #[test]
+    #[cfg(feature = "macros")]
+    fn escaped_panic() {
+        let report = run_with_report("crash_report::escaped_panic", escapes);
+        check_common(&report);
+        assert!(
+            report
+                .get("message")
+                .as_str()
+                .starts_with("panic escaped #[nounwind] function `aborts::crash_report::escapes`"),
+            "{:?}",
+            report
+        );
+        check_location(report.get("location"));
+        let panic = report.get("panic");
+        let line = check_location(panic.get("location"));
+        assert_eq!(panic.get("message").as_str(), line.to_string());
+        assert_eq!(*report.get("sections"), Json::Array(Vec::new()));
+        check_backtrace(&report);
+    }
+
+    #[test]
+    fn abort_unwind_section() {
+        let report = run_with_report("crash_report::abort_unwind_section", || {
+            nounwind::abort_unwind(|| panic!("{}", line!()));
+        });
+        check_common(&report);
+        assert_eq!(
+            report.get("message").as_str(),
+            "panic in a function that cannot unwind"
+        );
+        let section = check_location(report.get("location"));
+        let panic = report.get("panic");
+        let line = check_location(panic.get("location"));
+        assert_eq!(panic.get("message").as_str(), line.to_string());
+        assert_eq!(section, line);
+        match report.get("sections") {
+            Json::Array(sections) => {
+                assert_eq!(sections.len(), 1);
+                assert_eq!(check_location(&sections[0]), section);
+            }
+            other => panic!("expected array, got {:?}", other),
+        }
+        check_backtrace(&report);
+    }
+
+    #[test]
+    fn panic_nounwind() {
+        let report = run_with_report("crash_report::panic_nounwind", || {
+            nounwind::panic_nounwind!("fatal {}", line!());
+        });
+        check_common(&report);
+        let line = check_location(report.get("location"));
+        assert_eq!(report.get("message").as_str(), format!("fatal {}", line));
+        assert_eq!(*report.get("panic"), Json::Null);
+        check_backtrace(&report);
+    }
+
+    /// Reports are appended, so reports from other processes are kept.
+    #[test]
+    fn appends_to_file() {
+        let path = std::env::temp_dir().join(format!(
+            "nounwind-crash_report-appends_to_file-{}.json",
+            std::process::id()
+        ));
+        let output = run_child_with(
+            "crash_report::appends_to_file",
+            &[("NOUNWIND_CRASH_REPORT", path.to_str().unwrap())],
+            || {
+                let path = std::env::var_os("NOUNWIND_CRASH_REPORT").unwrap();
+                std::fs::write(path, "previous report\n").unwrap();
+                nounwind::panic_nounwind!("fatal error");
+            },
+        );
+        assert_aborted(&output);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let report = contents
+            .strip_prefix("previous report\n")
+            .unwrap_or_else(|| panic!("previous report truncated:\n{}", contents));
+        assert_eq!(Json::parse(report).get("message").as_str(), "fatal error");
+    }
+
+    /// A minimal JSON value, to avoid depending on a JSON library.
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+    impl Json {
+        fn parse(s: &str) -> Json {
+            let mut parser = Parser {
+                input: s.trim_end().as_bytes(),
+                pos: 0,
+            };
+            let value = parser.value();
+            assert_eq!(parser.pos, parser.input.len(), "trailing characters");
+            value
+        }
+
+        #[track_caller]
+        fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(fields) => match fields.iter().find(|(name, _)| name == key) {
+                    Some((_, value)) => value,
+                    None => panic!("missing field {:?} in {:?}", key, self),
+                },
+                _ => panic!("expected object, got {:?}", self),
+            }
+        }
+
+        #[track_caller]
+        fn as_str(&self) -> &str {
+            match self {
+                Json::String(s) => s,
+                _ => panic!("expected string, got {:?}", self),
+            }
+        }
+
+        #[track_caller]
+        fn as_u64(&self) -> u64 {
+            match *self {
+                Json::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as u64,
+                _ => panic!("expected integer, got {:?}", self),
+            }
+        }
+    }
+
+    struct Parser<'a> {
+        input: &'a [u8],
+        pos: usize,
+    }
+    impl Parser<'_> {
+        fn next(&mut self) -> u8 {
+            let b = self.input[self.pos];
+            self.pos += 1;
+            b
+        }
+
+        fn eat(&mut self, expected: &str) -> bool {
+            let found = self.input[self.pos..].starts_with(expected.as_bytes());
+            if found {
+                self.pos += expected.len();
+            }
+            found
+        }
+
+        fn value(&mut self) -> Json {
+            let first = self.input[self.pos];
+            match first {
+                b'{' => {
+                    self.pos += 1;
+                    let mut fields = Vec::new();
+                    while !self.eat("}") {
+                        if !fields.is_empty() {
+                            assert!(self.eat(","), "expected comma at {}", self.pos);
+                        }
+                        let name = self.string();
+                        assert!(self.eat(":"), "expected colon at {}", self.pos);
+                        fields.push((name, self.value()));
+                    }
+                    Json::Object(fields)
+                }
+                b'[' => {
+                    self.pos += 1;
+                    let mut items = Vec::new();
+                    while !self.eat("]") {
+                        if !items.is_empty() {
+                            assert!(self.eat(","), "expected comma at {}", self.pos);
+                        }
+                        items.push(self.value());
+                    }
+                    Json::Array(items)
+                }
+                b'"' => Json::String(self.string()),
+                _ if self.eat("null") => Json::Null,
+                _ if self.eat("true") => Json::Bool(true),
+                _ if self.eat("false") => Json::Bool(false),
+                _ => {
+                    let start = self.pos;
+                    while self.pos < self.input.len()
+                        && matches!(
+                            self.input[self.pos],
+                            b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+                        )
+                    {
+                        self.pos += 1;
+                    }
+                    let number = std::str::from_utf8(&self.input[start..self.pos]).unwrap();
+                    Json::Number(
+                        number
+                            .parse()
+                            .unwrap_or_else(|_| panic!("invalid value at {}", start)),
+                    )
+                }
+            }
+        }
+
+        fn string(&mut self) -> String {
+            assert_eq!(self.next(), b'"', "expected string at {}", self.pos);
+            let mut bytes = Vec::new();
+            loop {
+                match self.next() {
+                    b'"' => break,
+                    b'\\' => match self.next() {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'u' => {
+                            let hex =
+                                std::str::from_utf8(&self.input[self.pos..self.pos + 4]).unwrap();
+                            self.pos += 4;
+                            let c = char::from_u32(u32::from_str_radix(hex, 16).unwrap()).unwrap();
+                            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        b => bytes.push(b),
+                    },
+                    b => bytes.push(b),
+                }
+            }
+            String::from_utf8(bytes).unwrap()
+        }
+    }
+}
@@ -27,3 +27,362 @@ fn nopanic_macro() {
     }
     println!("res {res}");
 }
+
+#[cfg(feature = "macros")]
+struct Accumulator {
+    total: u32,
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind]
+impl Accumulator {
+    fn add(&mut self, val: u32) -> &mut Self {
+        self.total = self.total.checked_add(val).unwrap();
+        self
+    }
+
+    #[nounwind::nounwind(skip)]
+    fn total(&self) -> u32 {
+        self.total
+    }
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_impl_block() {
+    let mut acc = Accumulator { total: 0 };
+    acc.add(3).add(4);
+    assert_eq!(acc.total(), 7);
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind]
+trait Summable {
+    fn values(&self) -> &[u32];
+
+    fn sum(&self) -> u32 {
+        self.values().iter().sum()
+    }
+
+    #[nounwind::nounwind(skip)]
+    fn len(&self) -> usize {
+        self.values().len()
+    }
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind]
+impl Summable for Vec<u32> {
+    fn values(&self) -> &[u32] {
+        self
+    }
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_trait() {
+    let values = vec![1, 7, 2];
+    assert_eq!(values.sum(), 10);
+    assert_eq!(Summable::len(&values), 3);
+}
+
+/// Minimal executor for testing async code, without needing a dependency.
+fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = Box::pin(fut);
+    loop {
+        if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+            return res;
+        }
+    }
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind]
+async fn async_parse(s: &str) -> Result<u32, std::num::ParseIntError> {
+    let x: u32 = s.parse()?;
+    let y = async { 7 }.await;
+    Ok(x + y)
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind]
+async fn async_sum((a, b): (u32, u32), _: ()) -> u32 {
+    a + b
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_async() {
+    assert_eq!(block_on(async_parse("3")), Ok(10));
+    assert!(block_on(async_parse("foo")).is_err());
+    assert_eq!(block_on(async_sum((3, 4), ())), 7);
+}
+
+#[test]
+fn nopanic_future() {
+    use nounwind::future::FutureExt;
+    let fut = async {
+        let x = async { 3 }.abort_on_unwind().await;
+        x + 4
+    };
+    assert_eq!(block_on(fut.abort_on_unwind()), 7);
+}
+
+#[cfg(feature = "futures-core")]
+#[test]
+fn nopanic_stream() {
+    use futures_core::Stream;
+    use nounwind::future::StreamExt;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    struct Countdown(u32);
+    impl Stream for Countdown {
+        type Item = u32;
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<u32>> {
+            if self.0 == 0 {
+                Poll::Ready(None)
+            } else {
+                self.0 -= 1;
+                Poll::Ready(Some(self.0))
+            }
+        }
+    }
+    let mut stream = Box::pin(Countdown(3).abort_on_unwind());
+    let mut items = Vec::new();
+    while let Some(item) = block_on(std::future::poll_fn(|cx| stream.as_mut().poll_next(cx))) {
+        items.push(item);
+    }
+    assert_eq!(items, [2, 1, 0]);
+}
+
+#[cfg(feature = "macros")]
+fn unexpected_panic(_panic: &nounwind::EscapedPanic<'_>) {
+    unreachable!("on_panic handler should not be called");
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind(message = "should not abort", on_panic = unexpected_panic)]
+fn nopanic_args_helper(values: &[u32]) -> Option<u32> {
+    let mut total = 0u32;
+    for val in values {
+        total = total.checked_add(*val)?;
+    }
+    Some(total)
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind(message = "should not abort")]
+async fn nopanic_args_async_helper(x: u32) -> u32 {
+    async { x * 2 }.await
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_macro_args() {
+    assert_eq!(nopanic_args_helper(&[1, 7, 2]), Some(10));
+    assert_eq!(nopanic_args_helper(&[u32::MAX, 1]), None);
+    assert_eq!(block_on(nopanic_args_async_helper(3)), 6);
+}
+
+#[cfg(feature = "macros")]
+mod facade {
+    pub use nounwind;
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind(crate = crate::facade::nounwind)]
+fn nopanic_crate_path_helper(x: u32) -> u32 {
+    x + 1
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_crate_path() {
+    assert_eq!(nopanic_crate_path_helper(7), 8);
+    let add = crate::facade::nounwind::closure!(crate = crate::facade::nounwind, |x: u32| x + 1);
+    assert_eq!(add(7), 8);
+}
+
+// `#[nounwind] const fn` requires labeled blocks
+#[cfg(all(feature = "macros", nounwind_label_break_value))]
+#[nounwind::nounwind]
+const fn const_clamp(x: u32, max: u32) -> u32 {
+    if x > max {
+        return max;
+    }
+    x
+}
+
+#[cfg(all(feature = "macros", nounwind_label_break_value))]
+struct ConstWrapper(u32);
+
+#[cfg(all(feature = "macros", nounwind_label_break_value))]
+impl ConstWrapper {
+    #[nounwind::nounwind(message = "should not abort")]
+    const fn new(x: u32) -> Self {
+        ConstWrapper(const_clamp(x, 10))
+    }
+}
+
+#[cfg(all(feature = "macros", nounwind_label_break_value))]
+#[test]
+fn nopanic_const_fn() {
+    const CLAMPED: u32 = const_clamp(15, 10);
+    const WRAPPED: ConstWrapper = ConstWrapper::new(7);
+    assert_eq!(CLAMPED, 10);
+    assert_eq!(WRAPPED.0, 7);
+    assert_eq!(const_clamp(3, 10), 3);
+    assert_eq!(ConstWrapper::new(20).0, 10);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_closure_macro() {
+    fn call_fn(func: impl Fn(u32, u32) -> u32) -> u32 {
+        func(1, 2) + func(3, 4)
+    }
+    fn call_fn_mut(mut func: impl FnMut(u32)) {
+        func(1);
+        func(2);
+    }
+    fn call_fn_once(func: impl FnOnce() -> String) -> String {
+        func()
+    }
+    let offset = 10;
+    assert_eq!(call_fn(nounwind::closure!(|a, b| a + b + offset)), 30);
+    let mut total = 0;
+    call_fn_mut(nounwind::closure!(|x| total += x));
+    assert_eq!(total, 3);
+    let owned = String::from("owned");
+    assert_eq!(call_fn_once(nounwind::closure!(move || owned)), "owned");
+    let explicit = nounwind::closure!(|x: u32| -> Option<u32> {
+        if x == 0 {
+            return None;
+        }
+        Some(x * 2)
+    });
+    assert_eq!(explicit(0), None);
+    assert_eq!(explicit(4), Some(8));
+}
+
+#[cfg(all(feature = "macros", feature = "std"))]
+#[nounwind::nounwind]
+#[track_caller]
+fn nopanic_caller_location() -> &'static std::panic::Location<'static> {
+    std::panic::Location::caller()
+}
+
+#[cfg(all(feature = "macros", feature = "std"))]
+#[track_caller]
+#[nounwind::nounwind(message = "should not abort")]
+fn nopanic_caller_location_args(x: Option<u32>) -> Option<&'static std::panic::Location<'static>> {
+    x?;
+    Some(std::panic::Location::caller())
+}
+
+#[cfg(all(feature = "macros", feature = "std"))]
+#[test]
+fn nopanic_track_caller() {
+    let (location, line) = (nopanic_caller_location(), line!());
+    assert_eq!(location.file(), file!());
+    assert_eq!(location.line(), line);
+    assert!(nopanic_caller_location_args(None).is_none());
+    let (location, line) = (nopanic_caller_location_args(Some(1)).unwrap(), line!());
+    assert_eq!(location.line(), line);
+}
+
+#[cfg(feature = "macros")]
+#[nounwind::nounwind(wrapper = nopanic_sum_pairs_nounwind)]
+fn nopanic_sum_pairs<T: Into<u64>, const N: usize>(
+    pairs: [(T, T); N],
+    (offset, _): (u64, ()),
+) -> u64 {
+    pairs
+        .into_iter()
+        .map(|(a, b)| a.into() + b.into())
+        .sum::<u64>()
+        + offset
+}
+
+#[cfg(feature = "macros")]
+struct Celsius(f64);
+
+#[cfg(feature = "macros")]
+impl Celsius {
+    #[nounwind::nounwind(wrapper = to_fahrenheit_nounwind, message = "should not abort")]
+    fn to_fahrenheit(&self, offset: impl Into<f64>) -> f64 {
+        let offset = offset.into();
+        self.0 * 9.0 / 5.0 + 32.0 + offset
+    }
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn nopanic_wrapper() {
+    assert_eq!(
+        nopanic_sum_pairs_nounwind([(1u32, 2u32), (3, 4)], (5, ())),
+        15
+    );
+    assert_eq!(Celsius(100.0).to_fahrenheit_nounwind(1.0), 213.0);
+}
+
+#[test]
+fn nopanic_abort_guard() {
+    let guard = nounwind::AbortGuard::new("should not abort");
+    assert!(guard
+        .to_string()
+        .starts_with("should not abort (guard created at "));
+    guard.defuse();
+    let guard = nounwind::AbortGuard::at(std::panic::Location::caller());
+    guard.defuse();
+}
+
+#[cfg(any(feature = "std", feature = "global-panic-count"))]
+#[test]
+fn nopanic_unwind_guard() {
+    fn early_return(values: &[u32]) -> Option<u32> {
+        let _guard = nounwind::UnwindGuard::new("should not abort");
+        let first = values.first()?;
+        Some(first + 1)
+    }
+    assert_eq!(early_return(&[]), None);
+    assert_eq!(early_return(&[1]), Some(2));
+    assert!(!nounwind::unwind::panicking());
+}
+
+#[test]
+fn nopanic_unwrap_ext() {
+    use nounwind::{OptionExt, ResultExt};
+    assert_eq!(Some(3).unwrap_nounwind(), 3);
+    assert_eq!(Some(3).expect_nounwind("missing"), 3);
+    assert_eq!("7".parse::<u32>().unwrap_nounwind(), 7);
+    assert_eq!("7".parse::<u32>().expect_nounwind("invalid"), 7);
+    assert!("x"
+        .parse::<u32>()
+        .unwrap_err_nounwind()
+        .to_string()
+        .contains("invalid digit"));
+}
+
+#[test]
+fn nopanic_assertion_macros() {
+    let value = Some(7);
+    nounwind::assert_eq_nounwind!(value, Some(7));
+    nounwind::assert_eq_nounwind!(value, Some(7), "unexpected {:?}", value);
+    nounwind::assert_ne_nounwind!(value, None);
+    nounwind::assert_ne_nounwind!(value, None, "unexpected {:?}", value);
+    nounwind::assert_matches_nounwind!(value, Some(_));
+    nounwind::assert_matches_nounwind!(value, Some(1..=9) | None);
+    nounwind::assert_matches_nounwind!(value, Some(x) if x > 3, "too small");
+    nounwind::debug_assert_nounwind!(value.is_some());
+    nounwind::debug_assert_eq_nounwind!(value, Some(7));
+    nounwind::debug_assert_ne_nounwind!(value, None);
+}
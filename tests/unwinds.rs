@@ -0,0 +1,154 @@
+//! Test code that is allowed to unwind, where `#[nounwind]` is disabled.
+//!
+//! These panics are caught, so they don't abort the test harness.
+#![cfg(feature = "macros")]
+
+trait MaybeUnwind {
+    const NOUNWIND: bool;
+
+    fn run(&self) -> u32;
+}
+
+struct Unwinds;
+
+impl MaybeUnwind for Unwinds {
+    const NOUNWIND: bool = false;
+
+    fn run(&self) -> u32 {
+        panic!("allowed to unwind")
+    }
+}
+
+struct Succeeds;
+
+impl MaybeUnwind for Succeeds {
+    const NOUNWIND: bool = true;
+
+    fn run(&self) -> u32 {
+        7
+    }
+}
+
+#[nounwind::nounwind(if = T::NOUNWIND)]
+fn run_conditional<T: MaybeUnwind>(value: &T) -> u32 {
+    value.run()
+}
+
+#[nounwind::nounwind(cfg(any()))]
+fn never_nounwind() {
+    panic!("allowed to unwind")
+}
+
+#[test]
+fn conditional_nounwind() {
+    assert_eq!(run_conditional(&Succeeds), 7);
+    // a disabled #[nounwind] is allowed to unwind
+    assert!(std::panic::catch_unwind(|| run_conditional(&Unwinds)).is_err());
+    assert!(std::panic::catch_unwind(never_nounwind).is_err());
+}
+
+#[nounwind::nounwind(wrapper = checked_div_nounwind, abi = "C", no_mangle)]
+fn checked_div(a: u32, b: u32) -> u32 {
+    assert!(b != 0, "division by zero");
+    a / b
+}
+
+#[nounwind::nounwind(cfg(any()), wrapper = never_nounwind_wrapper)]
+fn never_nounwind_wrapped() {
+    panic!("allowed to unwind")
+}
+
+#[test]
+fn disabled_wrapper_unwinds() {
+    // the wrapper still exists, but is allowed to unwind
+    assert!(std::panic::catch_unwind(never_nounwind_wrapper).is_err());
+}
+
+#[test]
+fn original_of_wrapper_unwinds() {
+    assert_eq!(checked_div(6, 3), 2);
+    assert_eq!(checked_div_nounwind(6, 3), 2);
+    // the original function is unchanged
+    assert!(std::panic::catch_unwind(|| checked_div(1, 0)).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn unwind_guard_created_while_panicking() {
+    struct DropGuard;
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            // created during unwinding, so this must not abort
+            let _guard = nounwind::UnwindGuard::new("should not abort");
+        }
+    }
+    assert!(std::panic::catch_unwind(|| {
+        let _drop = DropGuard;
+        panic!("allowed to unwind")
+    })
+    .is_err());
+}
+
+#[cfg(feature = "std")]
+#[nounwind::catch_unwind(return = None)]
+fn parse_even(value: &str) -> Option<u32> {
+    let value: u32 = value.parse().unwrap();
+    assert!(value % 2 == 0, "{} is odd", value);
+    Some(value)
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn catch_unwind_returns_value() {
+    use nounwind::catch::{last_error, take_last_error};
+    assert_eq!(parse_even("4"), Some(4));
+    assert_eq!(parse_even("7"), None);
+    let error = take_last_error().unwrap();
+    assert_eq!(error.message(), "7 is odd");
+    assert_eq!(error.file(), Some(file!()));
+    assert!(last_error().is_none());
+    assert_eq!(
+        nounwind::catch_to(3, || -> u32 { panic!("allowed to unwind") }),
+        3
+    );
+    assert_eq!(last_error().unwrap().message(), "allowed to unwind");
+    // successful calls do not clear the error
+    assert_eq!(nounwind::catch_to(3, || 5), 5);
+    assert!(last_error().is_some());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn catch_to_ignores_stale_location() {
+    use nounwind::catch::take_last_error;
+    // the panic is caught by the inner call, rather than by `catch_to`
+    nounwind::catch_to((), || {
+        assert!(std::panic::catch_unwind(|| panic!("caught elsewhere")).is_err());
+    });
+    // resuming does not run the panic hook, so there is no location
+    nounwind::catch_to((), || std::panic::resume_unwind(Box::new("resumed")));
+    let error = take_last_error().unwrap();
+    assert_eq!(error.message(), "resumed");
+    assert_eq!(error.line(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn format_last_error_truncates() {
+    use nounwind::catch::{format_last_error, last_error, take_last_error};
+    use std::ffi::CStr;
+
+    let _ = take_last_error();
+    assert_eq!(unsafe { format_last_error(std::ptr::null_mut(), 0) }, -1);
+    nounwind::catch_to((), || panic!("0123456789"));
+    let full = last_error().unwrap().to_string();
+    let len = unsafe { format_last_error(std::ptr::null_mut(), 0) };
+    assert_eq!(len, full.len() as isize);
+    let mut buf = [1; 5];
+    assert_eq!(
+        unsafe { format_last_error(buf.as_mut_ptr(), buf.len()) },
+        len
+    );
+    let truncated = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    assert_eq!(truncated.to_bytes(), b"0123");
+}